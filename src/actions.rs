@@ -0,0 +1,422 @@
+use anyhow::Context;
+use humansize::{format_size, BINARY};
+use largest_file_finder::index::unix_secs;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// System locations no action will ever touch, whatever the scan root is.
+const DEFAULT_DENY: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/proc", "/sbin", "/sys", "/usr",
+];
+
+/// How actions are confirmed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Confirm {
+    /// Report what would happen without touching anything.
+    DryRun,
+    /// Act on every item without prompting.
+    Yes,
+    /// Prompt on the terminal before each item.
+    Interactive,
+}
+
+impl Confirm {
+    pub fn from_flags(yes: bool, interactive: bool) -> Self {
+        if yes {
+            Confirm::Yes
+        } else if interactive {
+            Confirm::Interactive
+        } else {
+            Confirm::DryRun
+        }
+    }
+}
+
+/// Refuses paths outside the scan root or under a denied directory.
+#[derive(Debug, Clone)]
+pub struct Guard {
+    root: PathBuf,
+    deny: Vec<PathBuf>,
+}
+
+impl Guard {
+    pub fn new(root: &Path, extra_deny: &[PathBuf]) -> anyhow::Result<Self> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve scan root: {}", root.display()))?;
+        let deny = DEFAULT_DENY
+            .iter()
+            .map(PathBuf::from)
+            .chain(extra_deny.iter().cloned())
+            // Resolve symlinked system dirs (e.g. /lib -> /usr/lib) so both spellings are caught.
+            .flat_map(|d| {
+                let resolved = d.canonicalize().ok();
+                std::iter::once(d).chain(resolved)
+            })
+            .collect();
+        Ok(Self { root, deny })
+    }
+
+    /// The resolved scan root.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the resolved path to act on, or the reason it was refused.
+    ///
+    /// The parent directory is resolved but the final component is not, so a symlinked result
+    /// refers to the link itself.
+    pub fn check(&self, path: &Path) -> Result<PathBuf, String> {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err("not a file path".to_string());
+        };
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        let resolved = parent
+            .canonicalize()
+            .map_err(|e| format!("cannot resolve parent directory: {e}"))?
+            .join(name);
+
+        if !resolved.starts_with(&self.root) {
+            return Err(format!("outside scan root {}", self.root.display()));
+        }
+        if let Some(denied) = self.deny.iter().find(|d| resolved.starts_with(d)) {
+            return Err(format!("under denied directory {}", denied.display()));
+        }
+        Ok(resolved)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Done,
+    Declined,
+    Refused,
+    /// The file changed or vanished between the scan and the action.
+    Changed,
+    /// The action chose not to act (e.g. the file would not compress well enough).
+    Skipped,
+    Failed,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Done => "done",
+            Outcome::Declined => "declined",
+            Outcome::Refused => "refused",
+            Outcome::Changed => "changed",
+            Outcome::Skipped => "skipped",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// Error an action returns to leave a file alone on purpose; recorded as [`Outcome::Skipped`].
+#[derive(Debug)]
+pub struct Skip(pub String);
+
+impl std::fmt::Display for Skip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Skip {}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    pub action: String,
+    pub path: String,
+    pub size: u64,
+    pub outcome: Outcome,
+    /// Where the file went, for actions that move it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Append-only JSONL log of every action attempted (dry runs are not logged).
+pub struct AuditLog {
+    file: File,
+}
+
+impl AuditLog {
+    /// `$XDG_STATE_HOME/largest-file-finder/audit.jsonl`, falling back to `~/.local/state`.
+    pub fn default_path() -> Option<PathBuf> {
+        let state = std::env::var_os("XDG_STATE_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))?;
+        Some(state.join("largest-file-finder").join("audit.jsonl"))
+    }
+
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).with_context(|| {
+                format!("Failed to create audit log directory: {}", dir.display())
+            })?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log: {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, rec: &AuditRecord) -> anyhow::Result<()> {
+        // One write per line so concurrent appenders never interleave records.
+        let mut line = serde_json::to_vec(rec).context("Failed to encode audit record")?;
+        line.push(b'\n');
+        self.file
+            .write_all(&line)
+            .context("Failed to append to audit log")
+    }
+}
+
+/// Safety checks and logging shared by every action.
+pub struct ActionContext {
+    pub guard: Guard,
+    pub audit: Option<AuditLog>,
+}
+
+impl ActionContext {
+    /// Checks the path, re-verifies it is still the scanned file, performs the action and logs
+    /// the result.
+    ///
+    /// `perform` receives the resolved path and returns where the file went, if anywhere.
+    pub fn apply(
+        &mut self,
+        action: &str,
+        path: &Path,
+        size: u64,
+        perform: impl FnOnce(&Path) -> anyhow::Result<Option<PathBuf>>,
+    ) -> anyhow::Result<(Outcome, Option<String>)> {
+        // Logged as resolved, so the record names the file acted on whatever the current
+        // directory; only a refused path is logged as given.
+        let (logged, outcome, target, detail) = match self.guard.check(path) {
+            Err(reason) => (path.to_path_buf(), Outcome::Refused, None, Some(reason)),
+            Ok(resolved) => {
+                let (outcome, target, detail) = Self::perform_checked(&resolved, size, perform);
+                (resolved, outcome, target, detail)
+            }
+        };
+        self.log(action, &logged, size, outcome, target, detail.clone())?;
+        Ok((outcome, detail))
+    }
+
+    /// Re-verifies `resolved` is still the scanned file and performs the action on it.
+    fn perform_checked(
+        resolved: &Path,
+        size: u64,
+        perform: impl FnOnce(&Path) -> anyhow::Result<Option<PathBuf>>,
+    ) -> (Outcome, Option<PathBuf>, Option<String>) {
+        match std::fs::symlink_metadata(resolved) {
+            Ok(md) if md.is_file() && md.len() == size => match perform(resolved) {
+                Ok(target) => (Outcome::Done, target, None),
+                Err(err) => match err.downcast::<Skip>() {
+                    Ok(Skip(reason)) => (Outcome::Skipped, None, Some(reason)),
+                    Err(err) => (Outcome::Failed, None, Some(format!("{err:#}"))),
+                },
+            },
+            Ok(md) if md.is_file() => (
+                Outcome::Changed,
+                None,
+                Some(format!("size changed to {} bytes", md.len())),
+            ),
+            Ok(_) => (
+                Outcome::Changed,
+                None,
+                Some("no longer a regular file".to_string()),
+            ),
+            Err(e) => (Outcome::Changed, None, Some(e.to_string())),
+        }
+    }
+
+    pub fn log(
+        &mut self,
+        action: &str,
+        path: &Path,
+        size: u64,
+        outcome: Outcome,
+        target: Option<PathBuf>,
+        detail: Option<String>,
+    ) -> anyhow::Result<()> {
+        let Some(audit) = self.audit.as_mut() else {
+            return Ok(());
+        };
+        audit.record(&AuditRecord {
+            time: unix_secs(std::time::SystemTime::now()).unwrap_or(0),
+            action: action.to_string(),
+            path: path.to_string_lossy().into_owned(),
+            size,
+            outcome,
+            target: target.map(|t| t.to_string_lossy().into_owned()),
+            detail,
+        })
+    }
+}
+
+/// Applies `perform` to each `(path, scanned size)` item according to `confirm`, printing one
+/// line per item and a summary. `verb` is the past tense shown to the user (e.g. "deleted").
+pub fn run_on_results<'a>(
+    ctx: &mut ActionContext,
+    action: &str,
+    verb: &str,
+    items: impl IntoIterator<Item = (&'a Path, u64)>,
+    confirm: Confirm,
+    mut perform: impl FnMut(&Path) -> anyhow::Result<Option<PathBuf>>,
+) -> anyhow::Result<()> {
+    let mut done: u64 = 0;
+    let mut done_bytes: u64 = 0;
+    let mut skipped: u64 = 0;
+    let stdin = std::io::stdin();
+
+    for (path, size) in items {
+        let size_str = format_size(size, BINARY);
+        match confirm {
+            Confirm::DryRun => {
+                if let Err(reason) = ctx.guard.check(path) {
+                    println!("refuse\t{size_str}\t{} ({reason})", path.display());
+                    skipped += 1;
+                } else {
+                    println!("would {action}\t{size_str}\t{}", path.display());
+                    done += 1;
+                    done_bytes += size;
+                }
+                continue;
+            }
+            Confirm::Interactive => {
+                print!("{action} {} ({size_str})? [y/N/q] ", path.display());
+                std::io::stdout().flush().ok();
+                let mut answer = String::new();
+                stdin.lock().read_line(&mut answer)?;
+                match answer.trim() {
+                    "y" | "Y" | "yes" => {}
+                    "q" | "Q" => break,
+                    _ => {
+                        let resolved = ctx.guard.check(path).unwrap_or_else(|_| path.into());
+                        ctx.log(action, &resolved, size, Outcome::Declined, None, None)?;
+                        skipped += 1;
+                        continue;
+                    }
+                }
+            }
+            Confirm::Yes => {}
+        }
+
+        let (outcome, detail) = ctx.apply(action, path, size, &mut perform)?;
+        if outcome == Outcome::Done {
+            println!("{verb}\t{size_str}\t{}", path.display());
+            done += 1;
+            done_bytes += size;
+        } else {
+            println!(
+                "{}\t{size_str}\t{} ({})",
+                outcome.as_str(),
+                path.display(),
+                detail.unwrap_or_default()
+            );
+            skipped += 1;
+        }
+    }
+
+    if confirm == Confirm::DryRun {
+        println!(
+            "Dry run: would {action} {done} files ({}), {skipped} refused; pass --yes or --interactive to proceed",
+            format_size(done_bytes, BINARY)
+        );
+    } else {
+        println!(
+            "Summary: {verb} {done} files ({}), {skipped} skipped",
+            format_size(done_bytes, BINARY)
+        );
+    }
+    Ok(())
+}
+
+/// Reads every well-formed record of an audit log, oldest first.
+pub fn read_log(path: &Path) -> anyhow::Result<Vec<AuditRecord>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open audit log: {}", path.display()))?;
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.context("Failed to read audit log")?;
+        if let Ok(rec) = serde_json::from_str(&line) {
+            records.push(rec);
+        }
+    }
+    Ok(records)
+}
+
+pub fn delete_file(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    std::fs::remove_file(path).with_context(|| format!("Failed to delete {}", path.display()))?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_refuses_paths_outside_root_and_on_deny_list() {
+        let base = std::env::temp_dir().join(format!("lff-guard-{}", std::process::id()));
+        let inside = base.join("scan");
+        let outside = base.join("other");
+        std::fs::create_dir_all(&inside).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+
+        let guard = Guard::new(&inside, &[inside.join("keep")]).unwrap();
+        assert!(guard.check(&inside.join("a.bin")).is_ok());
+        assert!(guard.check(&inside.join("../other/a.bin")).is_err());
+        assert!(guard.check(&outside.join("a.bin")).is_err());
+
+        std::fs::create_dir_all(inside.join("keep")).unwrap();
+        assert!(guard.check(&inside.join("keep/a.bin")).is_err());
+
+        let root_guard = Guard::new(Path::new("/"), &[]).unwrap();
+        assert!(root_guard.check(Path::new("/etc/passwd")).is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn apply_logs_the_resolved_path() {
+        let base = std::env::temp_dir().join(format!("lff-apply-{}", std::process::id()));
+        std::fs::create_dir_all(base.join("scan/sub")).unwrap();
+        let base = base.canonicalize().unwrap();
+        std::fs::write(base.join("scan/a.bin"), b"abc").unwrap();
+        let log = base.join("audit.jsonl");
+
+        let mut ctx = ActionContext {
+            guard: Guard::new(&base.join("scan"), &[]).unwrap(),
+            audit: Some(AuditLog::open(&log).unwrap()),
+        };
+        let given = base.join("scan/sub/../a.bin");
+        let (outcome, _) = ctx.apply("delete", &given, 3, |_| Ok(None)).unwrap();
+        assert_eq!(outcome, Outcome::Done);
+        let outside = base.join("scan/../a.bin");
+        ctx.apply("delete", &outside, 3, |_| Ok(None)).unwrap();
+
+        let text = std::fs::read_to_string(&log).unwrap();
+        let paths: Vec<String> = text
+            .lines()
+            .map(|l| serde_json::from_str::<AuditRecord>(l).unwrap().path)
+            .collect();
+        let expected = [base.join("scan/a.bin"), outside];
+        assert_eq!(paths, expected.map(|p| p.to_string_lossy().into_owned()));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
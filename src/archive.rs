@@ -0,0 +1,269 @@
+//! List the members of tar, tar.gz and zip archives as virtual `ARCHIVE!/MEMBER` paths, so large
+//! members can be matched and ranked like ordinary files.
+
+use crate::{Error, Result};
+use std::cell::Cell;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Separates the archive path from the member path in virtual paths.
+pub const SEPARATOR: &str = "!/";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    /// Virtual path, e.g. `backup.tar.gz!/var/db/dump.sql`
+    pub path: String,
+    /// Uncompressed size
+    pub size: u64,
+    /// Bytes the member takes up inside the archive, when it is compressed. For tar.gz this is the
+    /// amount of the gzip stream consumed while reading the member, so it is approximate.
+    pub packed: Option<u64>,
+    pub mtime: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Kind::TarGz)
+    } else if name.ends_with(".tar") {
+        Some(Kind::Tar)
+    } else if name.ends_with(".zip") {
+        Some(Kind::Zip)
+    } else {
+        None
+    }
+}
+
+/// True for file names this module knows how to open.
+pub fn is_archive(path: &Path) -> bool {
+    kind(path).is_some()
+}
+
+/// Calls `visit` for every regular file inside the archive at `path`, read from `file`.
+pub fn members(path: &Path, file: impl Read + Seek, mut visit: impl FnMut(Member)) -> Result<()> {
+    let Some(kind) = kind(path) else {
+        return Ok(());
+    };
+    let prefix = path.to_string_lossy();
+    match kind {
+        Kind::Tar => tar_members(&prefix, BufReader::new(file), None, &mut visit),
+        Kind::TarGz => {
+            let consumed = Rc::new(Cell::new(0));
+            let counted = Counting {
+                inner: BufReader::new(file),
+                count: Rc::clone(&consumed),
+            };
+            let gz = flate2::bufread::GzDecoder::new(counted);
+            tar_members(&prefix, gz, Some(&consumed), &mut visit)
+        }
+        Kind::Zip => zip_members(&prefix, BufReader::new(file), &mut visit),
+    }
+    .map_err(|source| Error::Archive {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn virtual_path(archive: &str, member: &str) -> String {
+    let member = member.trim_start_matches("./").trim_start_matches('/');
+    format!("{archive}{SEPARATOR}{member}")
+}
+
+fn tar_members(
+    prefix: &str,
+    reader: impl Read,
+    consumed: Option<&Rc<Cell<u64>>>,
+    visit: &mut impl FnMut(Member),
+) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    // The compressed size of a member is only known once the next header has been read.
+    let mut pending: Option<(Member, u64)> = None;
+    let position = || consumed.map_or(0, |c| c.get());
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if let Some((mut member, start)) = pending.take() {
+            member.packed = consumed.map(|_| position().saturating_sub(start));
+            visit(member);
+        }
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let member = Member {
+            path: virtual_path(prefix, &entry.path()?.to_string_lossy()),
+            size: entry.size(),
+            packed: None,
+            mtime: entry.header().mtime().ok().map(|t| t as i64),
+        };
+        pending = Some((member, position()));
+    }
+    if let Some((mut member, start)) = pending {
+        member.packed = consumed.map(|_| position().saturating_sub(start));
+        visit(member);
+    }
+    Ok(())
+}
+
+fn zip_members(
+    prefix: &str,
+    reader: impl Read + Seek,
+    visit: &mut impl FnMut(Member),
+) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for i in 0..archive.len() {
+        // Raw access only reads the headers; nothing is decompressed.
+        let file = archive.by_index_raw(i)?;
+        if !file.is_file() {
+            continue;
+        }
+        visit(Member {
+            path: virtual_path(prefix, file.name()),
+            size: file.size(),
+            packed: Some(file.compressed_size()),
+            mtime: file.last_modified().map(|dt| {
+                let days = days_from_civil(dt.year().into(), dt.month().into(), dt.day().into());
+                days * 86_400
+                    + i64::from(dt.hour()) * 3_600
+                    + i64::from(dt.minute()) * 60
+                    + i64::from(dt.second())
+            }),
+        });
+    }
+    Ok(())
+}
+
+/// Days since the Unix epoch of a proleptic Gregorian date (inverse of the conversion in
+/// `format_unix_secs`). Zip timestamps carry no zone and are treated as UTC.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Counts the bytes consumed through it. The decoder only consumes what it has decompressed, so
+/// this tracks the position in the compressed stream closely.
+struct Counting<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: BufRead> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Counting<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count.set(self.count.get() + amt as u64);
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let base = std::env::temp_dir().join(format!("lff-archive-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        base
+    }
+
+    fn list(path: &Path) -> Vec<Member> {
+        let mut found = Vec::new();
+        let file = File::open(path).unwrap();
+        members(path, file, |m| found.push(m)).unwrap();
+        found
+    }
+
+    #[test]
+    fn lists_tar_gz_members_with_virtual_paths() {
+        let base = scratch("tgz");
+        let path = base.join("backup.tar.gz");
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        for (name, len) in [("./var/db/dump.sql", 100_000), ("etc/motd", 10)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(len as u64);
+            header.set_mtime(1_700_000_000);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, vec![b'a'; len].as_slice())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let found = list(&path);
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[0].path,
+            format!("{}!/var/db/dump.sql", path.display())
+        );
+        assert_eq!(found[0].size, 100_000);
+        assert_eq!(found[0].mtime, Some(1_700_000_000));
+        assert!(found[0].packed.unwrap() < 100_000);
+        assert_eq!(found[1].path, format!("{}!/etc/motd", path.display()));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn lists_zip_members_with_sizes_and_times() {
+        let base = scratch("zip");
+        let path = base.join("photos.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let when = zip::DateTime::from_date_and_time(2024, 2, 29, 12, 30, 0).unwrap();
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .last_modified_time(when);
+        zip.add_directory("raw/", options).unwrap();
+        zip.start_file("raw/a.jpg", options).unwrap();
+        zip.write_all(&[1; 5000]).unwrap();
+        zip.finish().unwrap();
+
+        let found = list(&path);
+        assert_eq!(
+            found,
+            vec![Member {
+                path: format!("{}!/raw/a.jpg", path.display()),
+                size: 5000,
+                packed: Some(5000),
+                mtime: Some(1_709_209_800),
+            }]
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn recognises_archive_names() {
+        assert!(is_archive(Path::new("/b/Backup.TGZ")));
+        assert!(is_archive(Path::new("x.tar")));
+        assert!(!is_archive(Path::new("x.tar.zst")));
+    }
+}
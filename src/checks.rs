@@ -0,0 +1,325 @@
+//! `--fail-if` checks: thresholds over the matched files and the scan, for CI and cron jobs.
+//!
+//! A check is either a query expression, violated by every matched file it matches (e.g.
+//! `size>2GB`), or a comparison on an aggregate: `total>50GB`, `count>1000` or `errors>0`.
+
+use anyhow::Context;
+use humansize::{format_size, BINARY};
+use largest_file_finder::{
+    parse_size_bytes, CmpOp, Collector, Expr, IndexEntry, QueryMacros, Record, Totals,
+};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+enum Condition {
+    File(Expr),
+    /// Bytes in all matched files
+    Total(CmpOp, u64),
+    /// Number of matched files
+    Count(CmpOp, u64),
+    /// Errors met while scanning
+    Errors(CmpOp, u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    source: String,
+    condition: Condition,
+}
+
+impl Check {
+    pub fn parse(source: &str, macros: &QueryMacros) -> anyhow::Result<Self> {
+        let trimmed = source.trim_start();
+        let word = trimmed
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(trimmed.len());
+        let aggregate = ["total", "count", "errors"]
+            .into_iter()
+            .find(|kw| trimmed[..word].eq_ignore_ascii_case(kw));
+        let Some(aggregate) = aggregate else {
+            return Ok(Self {
+                source: source.to_string(),
+                condition: Condition::File(Expr::parse_with_macros(source, macros)?),
+            });
+        };
+
+        let (op, rest) = CmpOp::split_prefix(trimmed[word..].trim_start())
+            .with_context(|| format!("Expected comparison operator after {aggregate}"))?;
+        let val = rest.trim();
+        if val.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+            anyhow::bail!("Unexpected trailing input after {aggregate}: {val}");
+        }
+        let count = || {
+            val.parse()
+                .with_context(|| format!("Invalid number: {val}"))
+        };
+        let condition = match aggregate {
+            "total" => Condition::Total(
+                op,
+                parse_size_bytes(val).with_context(|| format!("Invalid size literal: {val}"))?,
+            ),
+            "count" => Condition::Count(op, count()?),
+            _ => Condition::Errors(op, count()?),
+        };
+        Ok(Self {
+            source: source.to_string(),
+            condition,
+        })
+    }
+
+    /// A file check for sizes above `limit`, shown as `size>{label}`.
+    pub fn larger_than(label: &str, limit: u64) -> Self {
+        Self {
+            source: format!("size>{label}"),
+            condition: Condition::File(Expr::size(CmpOp::Gt, limit)),
+        }
+    }
+
+    /// Distinct per kind of check, and clear of 1 (runtime errors) and 2 (usage errors).
+    pub fn exit_code(&self) -> i32 {
+        match self.condition {
+            Condition::File(_) => 3,
+            Condition::Total(..) => 4,
+            Condition::Count(..) => 5,
+            Condition::Errors(..) => 6,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self.condition {
+            Condition::File(_) => "file",
+            Condition::Total(..) => "total",
+            Condition::Count(..) => "count",
+            Condition::Errors(..) => "errors",
+        }
+    }
+}
+
+/// One line of `--violations`.
+#[derive(Debug, Serialize)]
+pub struct Violation<'a> {
+    check: &'a str,
+    kind: &'static str,
+    pub exit_code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    /// The aggregate value, for aggregate checks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<u64>,
+}
+
+impl Violation<'_> {
+    /// Whether a file check flagged this, rather than an aggregate.
+    pub fn is_file(&self) -> bool {
+        self.kind == "file"
+    }
+}
+
+/// Evaluates `checks` in order; file checks yield one violation per offending file, largest first.
+pub fn evaluate<'a>(
+    checks: &'a [Check],
+    matched: &'a [IndexEntry],
+    errors: u64,
+) -> Vec<Violation<'a>> {
+    let mut totals = Totals::default();
+    for entry in matched {
+        totals.add(entry.size);
+    }
+    violations(checks, matched, totals, errors)
+}
+
+/// What the checks need from a pass, without keeping every match: the totals, and the entries
+/// some file check matches.
+pub struct CheckCollector<'a> {
+    checks: &'a [Check],
+    totals: Totals,
+    hits: Vec<IndexEntry>,
+}
+
+impl<'a> CheckCollector<'a> {
+    pub fn new(checks: &'a [Check]) -> Self {
+        Self {
+            checks,
+            totals: Totals::default(),
+            hits: Vec::new(),
+        }
+    }
+
+    /// Like [`evaluate`] over every entry collected.
+    pub fn evaluate(&self, errors: u64) -> Vec<Violation<'_>> {
+        violations(self.checks, &self.hits, self.totals, errors)
+    }
+}
+
+impl Collector for CheckCollector<'_> {
+    fn collect(&mut self, record: &Record) -> largest_file_finder::Result<()> {
+        let entry = &record.entry;
+        self.totals.add(entry.size);
+        let hit = self.checks.iter().any(|check| match &check.condition {
+            Condition::File(expr) => expr.eval(&entry.path, entry.size),
+            _ => false,
+        });
+        if hit {
+            self.hits.push(entry.clone());
+        }
+        Ok(())
+    }
+}
+
+/// `hits` holds at least the entries the file checks match; `totals` covers every match.
+fn violations<'a>(
+    checks: &'a [Check],
+    hits: &'a [IndexEntry],
+    totals: Totals,
+    errors: u64,
+) -> Vec<Violation<'a>> {
+    let mut violations = Vec::new();
+    for check in checks {
+        let violation = |path: Option<&'a str>, size: Option<u64>, actual: Option<u64>| Violation {
+            check: &check.source,
+            kind: check.kind(),
+            exit_code: check.exit_code(),
+            path,
+            size,
+            actual,
+        };
+        let (op, actual, limit) = match &check.condition {
+            Condition::File(expr) => {
+                let mut file_hits: Vec<&IndexEntry> =
+                    hits.iter().filter(|e| expr.eval(&e.path, e.size)).collect();
+                file_hits.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+                violations.extend(
+                    file_hits
+                        .into_iter()
+                        .map(|e| violation(Some(&e.path), Some(e.size), None)),
+                );
+                continue;
+            }
+            Condition::Total(op, limit) => (op, totals.bytes, *limit),
+            Condition::Count(op, limit) => (op, totals.files, *limit),
+            Condition::Errors(op, limit) => (op, errors, *limit),
+        };
+        if op.holds(actual, limit) {
+            violations.push(violation(None, None, Some(actual)));
+        }
+    }
+    violations
+}
+
+/// Summarizes the violations on stderr, one line per failed check, and writes them all as JSONL
+/// to `out` when given.
+pub fn report(
+    checks: &[Check],
+    violations: &[Violation],
+    out: Option<&Path>,
+) -> anyhow::Result<()> {
+    if let Some(out) = out {
+        let file = File::create(out)
+            .with_context(|| format!("Failed to create violations file: {}", out.display()))?;
+        let mut w = BufWriter::new(file);
+        for v in violations {
+            serde_json::to_writer(&mut w, v).context("Failed to write violations")?;
+            w.write_all(b"\n").context("Failed to write violations")?;
+        }
+        w.flush().context("Failed to write violations")?;
+    }
+
+    for check in checks {
+        let mut hits = violations.iter().filter(|v| v.check == check.source);
+        let Some(first) = hits.next() else {
+            continue;
+        };
+        let actual = first.actual.unwrap_or_default();
+        let detail = match check.condition {
+            Condition::File(_) => {
+                let largest = format!(
+                    "{} ({})",
+                    first.path.unwrap_or_default(),
+                    format_size(first.size.unwrap_or_default(), BINARY)
+                );
+                match hits.count() {
+                    0 => format!("1 file: {largest}"),
+                    more => format!("{} files, largest {largest}", more + 1),
+                }
+            }
+            Condition::Total(..) => format!("total is {}", format_size(actual, BINARY)),
+            _ => format!("{} is {actual}", check.kind()),
+        };
+        eprintln!(
+            "Check failed (exit {}): {}: {detail}",
+            check.exit_code(),
+            check.source
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_file_and_aggregate_checks() {
+        let checks: Vec<Check> = ["size>1KB AND ext:iso", "total>=3KB", "count>5", "errors>0"]
+            .iter()
+            .map(|c| Check::parse(c, &QueryMacros::new()).unwrap())
+            .collect();
+        let matched = [
+            IndexEntry::new("/a.iso", 1500),
+            IndexEntry::new("/b.iso", 2048),
+            IndexEntry::new("/c.txt", 4096),
+        ];
+
+        let violations = evaluate(&checks, &matched, 2);
+        let got: Vec<(i32, Option<&str>, Option<u64>)> = violations
+            .iter()
+            .map(|v| (v.exit_code, v.path, v.actual))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (3, Some("/b.iso"), None),
+                (3, Some("/a.iso"), None),
+                (4, None, Some(7644)),
+                (6, None, Some(2)),
+            ]
+        );
+
+        let mut collector = CheckCollector::new(&checks);
+        for entry in &matched {
+            collector.collect(&entry.clone().into()).unwrap();
+        }
+        assert_eq!(collector.hits.len(), 2);
+        let streamed: Vec<(i32, Option<&str>, Option<u64>)> = collector
+            .evaluate(2)
+            .iter()
+            .map(|v| (v.exit_code, v.path, v.actual))
+            .collect();
+        assert_eq!(streamed, got);
+
+        assert!(Check::parse("total>lots", &QueryMacros::new()).is_err());
+        assert!(Check::parse("count>5 AND size>1", &QueryMacros::new()).is_err());
+    }
+
+    #[test]
+    fn single_file_violation_is_written_to_the_violations_file() {
+        let checks = [Check::parse("size>1GB", &QueryMacros::new()).unwrap()];
+        let matched = [IndexEntry::new("/vm/disk.img", 2 << 30)];
+        let out = std::env::temp_dir().join(format!("lff-violations-{}.jsonl", std::process::id()));
+
+        let violations = evaluate(&checks, &matched, 0);
+        report(&checks, &violations, Some(&out)).unwrap();
+
+        assert_eq!(violations.first().map(|v| v.exit_code), Some(3));
+        assert!(violations.iter().all(|v| v.is_file()));
+        let written = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        assert!(written.contains("\"/vm/disk.img\""), "{written}");
+        std::fs::remove_file(&out).unwrap();
+    }
+}
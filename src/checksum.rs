@@ -0,0 +1,30 @@
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// SHA-256 of everything `reader` yields.
+pub fn hash_reader(mut reader: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+pub fn hash_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    hash_reader(file).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// True when both files have the same length and SHA-256.
+pub fn same_content(a: &Path, b: &Path) -> anyhow::Result<bool> {
+    let (ma, mb) = (std::fs::metadata(a)?, std::fs::metadata(b)?);
+    Ok(ma.len() == mb.len() && hash_file(a)? == hash_file(b)?)
+}
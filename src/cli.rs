@@ -0,0 +1,659 @@
+//! Command-line interface: the subcommands and the option groups they share.
+//!
+//! Without a subcommand the options of `scan` are accepted at the top level, so invocations from
+//! before the subcommands existed (`largest-file-finder /data --top 5`) keep working. A root named
+//! like a subcommand (`tui`, `stats`, ...) runs that subcommand instead; `scan ./tui` scans it.
+
+use crate::{compress, dedupe};
+use anyhow::Context;
+use clap::Parser;
+use largest_file_finder::{AgeField, Expr, Matcher, QueryMacros, Ranking, SortKey};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "largest-file-finder",
+    about = "Find the largest files under a directory",
+    after_help = "A directory named like a subcommand must be scanned as `scan ./NAME`."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub legacy: LegacyArgs,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+/// Rejects `scan` options given at the top level together with a subcommand (`--top 5 stats`),
+/// which would otherwise be ignored. Global options may go on either side of the subcommand.
+pub fn check_top_level(
+    cmd: &mut clap::Command,
+    matches: &clap::ArgMatches,
+) -> clap::error::Result<()> {
+    let Some(name) = matches.subcommand_name() else {
+        return Ok(());
+    };
+    let given = cmd.get_arguments().find(|a| {
+        !a.is_global_set()
+            && matches.value_source(a.get_id().as_str())
+                == Some(clap::parser::ValueSource::CommandLine)
+    });
+    match given {
+        Some(arg) => {
+            let arg = match arg.get_long() {
+                Some(long) => format!("--{long}"),
+                None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+            };
+            Err(cmd.error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("{arg} cannot be used with the `{name}` subcommand; give it after the subcommand instead"),
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Accepted by every subcommand.
+#[derive(clap::Args, Debug, Clone)]
+pub struct GlobalArgs {
+    /// Read option defaults and profiles from this TOML file (default:
+    /// $XDG_CONFIG_HOME/largest-file-finder/config.toml, if it exists)
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the options of this profile from the config file
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Print progress occasionally (paths skipped / visited)
+    #[arg(long, global = true, default_value_t = false)]
+    pub verbose: bool,
+}
+
+/// The top-level form: `scan`, plus the index options it used to take.
+#[derive(clap::Args, Debug, Clone)]
+pub struct LegacyArgs {
+    #[command(flatten)]
+    pub scan: ScanArgs,
+
+    /// Same as `index build` while scanning; deprecated
+    #[arg(long, hide = true, value_name = "FILE", conflicts_with = "index_read")]
+    pub index_write: Option<PathBuf>,
+
+    /// Same as `query FILE`; deprecated
+    #[arg(long, hide = true, value_name = "FILE")]
+    pub index_read: Option<PathBuf>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Scan a directory and rank its files (the default when no subcommand is given)
+    Scan(ScanArgs),
+    /// Build or refresh a JSONL index of every file, for `query`, `diff`, `stats`, ...
+    #[command(subcommand)]
+    Index(IndexCommand),
+    /// Rank the files in an index instead of scanning
+    Query(QueryArgs),
+    /// Compare two indexes: files added, removed, grown or shrunk, largest change first
+    Diff(DiffArgs),
+    /// Summarize the matched files: totals, size distribution and largest extensions
+    Stats(StatsArgs),
+    /// List sets of identical files among the matched files, most wasted space first
+    Dupes(DupesArgs),
+    /// Rescan periodically and report files entering the top N or changing size
+    Watch(WatchArgs),
+    /// Browse matched files in an interactive directory tree (from a live scan or --index)
+    Tui(TuiArgs),
+    /// Move files trashed by --trash back to their original location, using the audit log
+    ///
+    /// A dry run unless --yes or --interactive is given.
+    Restore {
+        /// Original paths to restore (default: everything still in the trash)
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        confirm: ConfirmArgs,
+
+        #[command(flatten)]
+        safety: SafetyArgs,
+    },
+    /// Rank the files of a container image (OCI layout directory or `docker save` tarball) and
+    /// its layers, flagging files that later layers delete or overwrite
+    Image {
+        /// OCI image layout directory or image tarball
+        path: PathBuf,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        #[command(flatten)]
+        rank: RankArgs,
+    },
+    /// Rank the files tracked or staged in a git repository (read from its index) and fail with
+    /// exit status 3 when one is larger than --max-size, e.g. from a pre-commit hook
+    GitCheck {
+        /// Any directory inside the work tree (default: current directory)
+        path: Option<PathBuf>,
+
+        /// Largest size allowed for a tracked file
+        #[arg(long, value_name = "SIZE", default_value = "10MB")]
+        max_size: String,
+
+        /// Exempt files matching this gitignore-style pattern from --max-size; repeatable
+        #[arg(long, value_name = "PATTERN")]
+        allow: Vec<String>,
+
+        /// Read --allow patterns from FILE, one per line (gitignore syntax)
+        #[arg(long, value_name = "FILE")]
+        allowlist: Option<PathBuf>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        #[command(flatten)]
+        rank: RankArgs,
+
+        #[command(flatten)]
+        checks: CheckArgs,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum IndexCommand {
+    /// Scan ROOT and write every file to a new index
+    Build {
+        /// Root directory to scan
+        root: PathBuf,
+
+        /// Index file to write (JSONL)
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
+        #[command(flatten)]
+        walk: WalkArgs,
+    },
+    /// Rescan ROOT into an existing index, reporting what changed since it was written
+    Update {
+        /// Index file to replace (JSONL)
+        index: PathBuf,
+
+        /// Root directory to scan
+        root: PathBuf,
+
+        #[command(flatten)]
+        walk: WalkArgs,
+    },
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScanArgs {
+    /// Root directory to scan (default: /)
+    #[arg(default_value = "/")]
+    pub root: PathBuf,
+
+    #[command(flatten)]
+    pub walk: WalkArgs,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    #[command(flatten)]
+    pub rank: RankArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub checks: CheckArgs,
+
+    #[command(flatten)]
+    pub actions: ActionArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct QueryArgs {
+    /// Index file to read (JSONL)
+    pub index: PathBuf,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    #[command(flatten)]
+    pub rank: RankArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub checks: CheckArgs,
+
+    #[command(flatten)]
+    pub actions: ActionArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiffArgs {
+    /// The earlier index
+    pub old: PathBuf,
+
+    /// The later index
+    pub new: PathBuf,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Number of changes to print
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Number of extensions to list
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DupesArgs {
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Number of duplicate sets to list
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct WatchArgs {
+    /// Root directory to scan (default: /)
+    #[arg(default_value = "/")]
+    pub root: PathBuf,
+
+    /// Seconds to wait between scans
+    #[arg(long, value_name = "SECS", default_value_t = 300)]
+    pub interval: u64,
+
+    /// Stop after this many scans (default: run until interrupted)
+    #[arg(long, value_name = "N")]
+    pub count: Option<u64>,
+
+    #[command(flatten)]
+    pub walk: WalkArgs,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    #[command(flatten)]
+    pub rank: RankArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct TuiArgs {
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    #[command(flatten)]
+    pub safety: SafetyArgs,
+}
+
+/// A directory to scan, or an index to read instead.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SourceArgs {
+    /// Root directory to scan (default: /)
+    #[arg(default_value = "/")]
+    pub root: PathBuf,
+
+    /// Read this index (JSONL) instead of scanning
+    #[arg(long, value_name = "FILE")]
+    pub index: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub walk: WalkArgs,
+}
+
+/// How the filesystem is walked.
+#[derive(clap::Args, Debug, Clone)]
+pub struct WalkArgs {
+    /// Follow symlinks while walking
+    #[arg(long, default_value_t = false)]
+    pub follow_symlinks: bool,
+
+    /// Do not cross filesystem boundaries (best-effort)
+    #[arg(long, default_value_t = false)]
+    pub one_file_system: bool,
+
+    /// Only scan filesystems of these types (comma-separated, e.g. ext4,xfs); see /proc/self/mountinfo
+    #[arg(long, value_name = "TYPES", value_delimiter = ',')]
+    pub fs_types: Vec<String>,
+
+    /// Skip filesystems of these types instead of the built-in list of pseudo filesystems (proc,
+    /// sysfs, cgroup2, devtmpfs, ...) and overlay backing directories; pass '' to skip none.
+    /// tmpfs (/run, /tmp, /dev/shm) is scanned unless listed here
+    #[arg(long, value_name = "TYPES", value_delimiter = ',')]
+    pub exclude_fs_types: Option<Vec<String>>,
+
+    /// Honor .gitignore, .ignore and .lffignore files (gitignore syntax, later files take
+    /// precedence); ignored directories are not descended into
+    #[arg(long, default_value_t = false)]
+    pub ignore_files: bool,
+
+    /// Also list the members of .tar, .tar.gz/.tgz and .zip files, as ARCHIVE!/MEMBER paths
+    #[arg(long, default_value_t = false)]
+    pub inspect_archives: bool,
+
+    /// Write every error met while scanning (unreadable directories, vanished files, ...) to this JSONL file
+    #[arg(long, value_name = "FILE")]
+    pub errors_log: Option<PathBuf>,
+}
+
+/// Which files are matched.
+#[derive(clap::Args, Debug, Clone)]
+pub struct FilterArgs {
+    /// Ignore files smaller than this many bytes
+    #[arg(long, default_value_t = 0)]
+    pub min_bytes: u64,
+
+    /// Boolean query expression (AND/OR/NOT, parentheses) over name/path regex + ext/size
+    ///
+    /// Examples:
+    ///   name:/\\.(mp4|mkv)$/ AND size>1GB
+    ///   path:/Downloads/ AND NOT name:/\\.part$/
+    ///   ext in (iso, img) AND size>=4GB
+    ///   @video AND size>1GB      (@name refers to [queries] in the config file)
+    #[arg(long, value_name = "EXPR")]
+    pub query: Option<String>,
+
+    /// Include filter (case-insensitive regex) applied to full path; repeatable
+    #[arg(long, value_name = "REGEX")]
+    pub include: Vec<String>,
+
+    /// Exclude filter (case-insensitive regex) applied to full path; repeatable
+    #[arg(long, value_name = "REGEX")]
+    pub exclude: Vec<String>,
+}
+
+impl FilterArgs {
+    pub fn matcher(&self, macros: &QueryMacros) -> anyhow::Result<Matcher> {
+        let mut matcher = Matcher::new();
+        for pat in &self.include {
+            matcher = matcher
+                .include(pat)
+                .with_context(|| format!("Invalid --include regex: {pat}"))?;
+        }
+        for pat in &self.exclude {
+            matcher = matcher
+                .exclude(pat)
+                .with_context(|| format!("Invalid --exclude regex: {pat}"))?;
+        }
+        if let Some(expr_str) = &self.query {
+            let expr = Expr::parse_with_macros(expr_str, macros)
+                .with_context(|| format!("Invalid --query expression: {expr_str}"))?;
+            matcher = matcher.with_query(expr);
+        }
+        Ok(matcher)
+    }
+}
+
+/// How the matched files are ranked.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RankArgs {
+    /// Number of top-ranked files to print
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+
+    /// Rank files by this key (largest, newest, longest or deepest first; paths A to Z)
+    ///
+    /// `score` ranks by size weighted by staleness: size * days_since(--score-age) ^ --score-exponent
+    #[arg(
+        long,
+        visible_alias = "rank-by",
+        value_enum,
+        default_value_t = SortKey::Size
+    )]
+    pub sort: SortKey,
+
+    /// Invert the ranking (smallest, oldest, shortest or shallowest first; paths Z to A)
+    #[arg(long, default_value_t = false)]
+    pub reverse: bool,
+
+    /// Timestamp used for the age term of `--sort score`
+    #[arg(long, value_enum, default_value_t = AgeField::Atime)]
+    pub score_age: AgeField,
+
+    /// Exponent applied to the age (in days) for `--sort score`; 0 ranks by size alone
+    #[arg(long, value_name = "F", default_value_t = 1.0, value_parser = parse_exponent)]
+    pub score_exponent: f64,
+}
+
+/// A finite, non-negative `--score-exponent`; a negative one would rank fresh files first.
+fn parse_exponent(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(exp) if exp.is_finite() && exp >= 0.0 => Ok(exp),
+        Ok(_) => Err("expected a finite number >= 0".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+impl RankArgs {
+    pub fn ranking(&self) -> Ranking {
+        Ranking::new(self.sort, self.reverse).with_score(self.score_age, self.score_exponent)
+    }
+}
+
+/// How the results are presented.
+#[derive(clap::Args, Debug, Clone)]
+pub struct OutputArgs {
+    /// Group the results by filesystem, with each filesystem's mount point, capacity and free space
+    #[arg(long, default_value_t = false)]
+    pub by_filesystem: bool,
+
+    /// Also write a report of the scan; FORMAT is `html` (a single offline file with a treemap)
+    #[arg(long, num_args = 2, value_names = ["FORMAT", "FILE"])]
+    pub report: Vec<String>,
+}
+
+/// Thresholds that make the run fail.
+#[derive(clap::Args, Debug, Clone)]
+pub struct CheckArgs {
+    /// Exit with a non-zero status when a check fails; repeatable
+    ///
+    /// A query expression fails for every matched file it matches (exit 3); `total`, `count` and
+    /// `errors` compare the matched bytes (exit 4), matched files (exit 5) and scan errors (exit 6).
+    /// The first failed check sets the status. Examples:
+    ///   size>2GB
+    ///   total>50GB
+    ///   errors>0
+    #[arg(long, value_name = "CHECK")]
+    pub fail_if: Vec<String>,
+
+    /// Write every --fail-if (or git-check) violation as JSONL to FILE
+    #[arg(long, value_name = "FILE")]
+    pub violations: Option<PathBuf>,
+}
+
+/// What to do with the ranked results.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ActionArgs {
+    /// Delete the ranked results (a dry run unless --yes or --interactive is given)
+    #[arg(long, default_value_t = false)]
+    pub delete: bool,
+
+    /// Move the ranked results to the freedesktop.org trash (a dry run unless --yes or --interactive)
+    #[arg(long, default_value_t = false, conflicts_with = "delete")]
+    pub trash: bool,
+
+    /// Move the ranked results under DIR, mirroring their path below the root (a dry run unless --yes or --interactive)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["delete", "trash"])]
+    pub relocate_to: Option<PathBuf>,
+
+    /// Compress the ranked results in place with CODEC, removing each original once the compressed file verifies (a dry run unless --yes or --interactive)
+    #[arg(
+        long,
+        value_name = "CODEC",
+        conflicts_with_all = ["delete", "trash", "relocate_to"]
+    )]
+    pub compress: Option<compress::Codec>,
+
+    /// Replace identical copies among the ranked results with links to the highest-ranked copy (a dry run unless --yes or --interactive)
+    #[arg(
+        long,
+        value_name = "MODE",
+        conflicts_with_all = ["delete", "trash", "relocate_to", "compress"]
+    )]
+    pub dedupe: Option<dedupe::LinkMode>,
+
+    /// With --compress, skip files whose compressed size would exceed this fraction of the original
+    #[arg(long, value_name = "RATIO", default_value_t = 0.9, value_parser = parse_ratio)]
+    pub compress_ratio: f64,
+
+    /// With --relocate-to, replace each moved file with a symlink to its new location
+    #[arg(long, default_value_t = false, requires = "relocate_to")]
+    pub leave_symlink: bool,
+
+    #[command(flatten)]
+    pub confirm: ConfirmArgs,
+
+    #[command(flatten)]
+    pub safety: SafetyArgs,
+}
+
+impl ActionArgs {
+    pub fn any(&self) -> bool {
+        self.delete
+            || self.trash
+            || self.relocate_to.is_some()
+            || self.compress.is_some()
+            || self.dedupe.is_some()
+    }
+}
+
+/// A `--compress-ratio` in (0, 1]; above 1 the "compressed" file could be larger.
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if ratio.is_finite() && ratio > 0.0 && ratio <= 1.0 => Ok(ratio),
+        Ok(_) => Err("expected a number > 0 and <= 1".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConfirmArgs {
+    /// Carry out actions on every result without prompting
+    #[arg(long, default_value_t = false, conflicts_with = "interactive")]
+    pub yes: bool,
+
+    /// Ask for confirmation before acting on each result
+    #[arg(long, default_value_t = false)]
+    pub interactive: bool,
+}
+
+/// Limits on what actions may touch, and where they are recorded.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SafetyArgs {
+    /// Only act on files under DIR (default: the scan root; required with an index, which does not
+    /// record the directory it was built from)
+    #[arg(long, value_name = "DIR")]
+    pub within: Option<PathBuf>,
+
+    /// Never act on files under this directory, in addition to /etc, /usr, ...; repeatable
+    #[arg(long, value_name = "DIR")]
+    pub deny: Vec<PathBuf>,
+
+    /// Append-only JSONL audit log of actions (default: $XDG_STATE_HOME/largest-file-finder/audit.jsonl)
+    #[arg(long, value_name = "FILE")]
+    pub audit_log: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn flat_invocation_still_means_scan() {
+        let cli = Cli::try_parse_from(["lff", "/data", "--top", "5", "--delete"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.legacy.scan.root, PathBuf::from("/data"));
+        assert_eq!(cli.legacy.scan.rank.top, 5);
+        assert!(cli.legacy.scan.actions.delete);
+
+        let cli = Cli::try_parse_from(["lff", "--index-read", "i.jsonl"]).unwrap();
+        assert_eq!(cli.legacy.index_read, Some(PathBuf::from("i.jsonl")));
+
+        let cli = Cli::try_parse_from([
+            "lff",
+            "index",
+            "build",
+            "/data",
+            "-o",
+            "i.jsonl",
+            "--verbose",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Index(IndexCommand::Build { .. }))
+        ));
+        assert!(cli.global.verbose);
+
+        let mut cmd = Cli::command();
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(["lff", "--verbose", "stats", "/data"]);
+        assert!(check_top_level(&mut cmd, &matches.unwrap()).is_ok());
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(["lff", "--top", "5", "stats", "/data"]);
+        assert!(check_top_level(&mut cmd, &matches.unwrap()).is_err());
+    }
+
+    #[test]
+    fn score_exponent_must_be_finite_and_non_negative() {
+        let exponent = |value: &str| {
+            Cli::try_parse_from(["lff", "scan", "--sort", "score", "--score-exponent", value]).map(
+                |cli| match cli.command {
+                    Some(Command::Scan(scan)) => scan.rank.score_exponent,
+                    _ => unreachable!(),
+                },
+            )
+        };
+        assert_eq!(exponent("0.5").unwrap(), 0.5);
+        assert_eq!(exponent("0").unwrap(), 0.0);
+        for bad in ["-1", "NaN", "inf", "x"] {
+            assert!(exponent(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn compress_ratio_must_be_a_fraction() {
+        let ratio = |value: &str| {
+            Cli::try_parse_from(["lff", "scan", "--compress-ratio", value]).map(|cli| {
+                match cli.command {
+                    Some(Command::Scan(scan)) => scan.actions.compress_ratio,
+                    _ => unreachable!(),
+                }
+            })
+        };
+        assert_eq!(ratio("0.5").unwrap(), 0.5);
+        assert_eq!(ratio("1").unwrap(), 1.0);
+        for bad in ["0", "-0.5", "1.5", "NaN", "inf", "x"] {
+            assert!(ratio(bad).is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! What a [`Pipeline`](crate::Pipeline) does with the files that pass its filters. Several
+//! collectors can share one pass, e.g. a [`TopN`], a [`GroupBy::extension`] breakdown and an
+//! [`IndexWriter`] over a single scan.
+
+use crate::source::Record;
+use crate::{IndexEntry, IndexWriter, Result, TopN};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Receives the records of a pipeline, one at a time.
+pub trait Collector {
+    fn collect(&mut self, record: &Record) -> Result<()>;
+
+    /// Whether to receive every record, not only those passing the filters; an index being
+    /// written wants them all.
+    fn wants_unmatched(&self) -> bool {
+        false
+    }
+
+    /// State worth showing in the progress lines printed with `verbose`.
+    fn progress(&self) -> Option<String> {
+        None
+    }
+}
+
+impl Collector for TopN {
+    fn collect(&mut self, record: &Record) -> Result<()> {
+        self.offer_at(record.path.clone(), &record.entry);
+        Ok(())
+    }
+
+    fn progress(&self) -> Option<String> {
+        let floor = self
+            .floor()
+            .map_or_else(|| "none".to_string(), |sp| self.ranking().describe(sp));
+        Some(format!(
+            "collected: {}, current top-floor: {floor}",
+            self.len()
+        ))
+    }
+}
+
+/// Keeps every matching entry.
+impl Collector for Vec<IndexEntry> {
+    fn collect(&mut self, record: &Record) -> Result<()> {
+        self.push(record.entry.clone());
+        Ok(())
+    }
+}
+
+impl<W: Write> Collector for IndexWriter<W> {
+    fn collect(&mut self, record: &Record) -> Result<()> {
+        self.write(&record.entry)
+    }
+
+    fn wants_unmatched(&self) -> bool {
+        true
+    }
+}
+
+/// A file count and the bytes they take.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl Totals {
+    pub fn add(&mut self, size: u64) {
+        self.files += 1;
+        self.bytes += size;
+    }
+}
+
+/// Totals per group, the group of an entry given by a key function.
+#[derive(Debug)]
+pub struct GroupBy<F = fn(&IndexEntry) -> String> {
+    key: F,
+    groups: HashMap<String, Totals>,
+}
+
+impl GroupBy {
+    /// Groups by lowercase extension, `""` for files without one.
+    pub fn extension() -> Self {
+        Self::new(|entry| {
+            Path::new(&entry.path)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl<F: FnMut(&IndexEntry) -> String> GroupBy<F> {
+    pub fn new(key: F) -> Self {
+        Self {
+            key,
+            groups: HashMap::new(),
+        }
+    }
+
+    pub fn groups(&self) -> &HashMap<String, Totals> {
+        &self.groups
+    }
+
+    /// Groups by bytes, largest first (ties by name), at most `top`.
+    pub fn largest(&self, top: usize) -> Vec<(&str, Totals)> {
+        let mut groups: Vec<(&str, Totals)> = self
+            .groups
+            .iter()
+            .map(|(key, totals)| (key.as_str(), *totals))
+            .collect();
+        groups.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        groups.truncate(top);
+        groups
+    }
+}
+
+impl<F: FnMut(&IndexEntry) -> String> Collector for GroupBy<F> {
+    fn collect(&mut self, record: &Record) -> Result<()> {
+        let key = (self.key)(&record.entry);
+        self.groups.entry(key).or_default().add(record.entry.size);
+        Ok(())
+    }
+}
+
+/// Totals per size range.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// Ascending lower bounds; sizes below the first count towards it
+    bounds: Vec<u64>,
+    buckets: Vec<Totals>,
+}
+
+impl Histogram {
+    /// One bucket per lower bound; the last one is open-ended.
+    pub fn new(mut bounds: Vec<u64>) -> Self {
+        bounds.sort_unstable();
+        bounds.dedup();
+        let buckets = vec![Totals::default(); bounds.len()];
+        Self { bounds, buckets }
+    }
+
+    /// `(low, high, totals)` per bucket, `high` exclusive and `None` for the last one.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, Option<u64>, Totals)> + '_ {
+        self.bounds
+            .iter()
+            .zip(&self.buckets)
+            .enumerate()
+            .map(|(idx, (low, totals))| (*low, self.bounds.get(idx + 1).copied(), *totals))
+    }
+}
+
+impl Collector for Histogram {
+    fn collect(&mut self, record: &Record) -> Result<()> {
+        let size = record.entry.size;
+        let idx = self
+            .bounds
+            .iter()
+            .rposition(|&low| size >= low)
+            .unwrap_or(0);
+        if let Some(bucket) = self.buckets.get_mut(idx) {
+            bucket.add(size);
+        }
+        Ok(())
+    }
+}
+
+/// Buckets files by size, the first step of finding duplicates: only files of equal size can be
+/// identical. Empty files and archive members are left out.
+#[derive(Debug, Default)]
+pub struct DuplicateBuckets {
+    by_size: HashMap<u64, Vec<Record>>,
+    /// Sizes in the order first seen
+    order: Vec<u64>,
+}
+
+impl DuplicateBuckets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The buckets holding more than one file, each in the order collected.
+    pub fn into_candidates(mut self) -> Vec<Vec<Record>> {
+        self.order
+            .iter()
+            .filter_map(|size| self.by_size.remove(size))
+            .filter(|bucket| bucket.len() > 1)
+            .collect()
+    }
+}
+
+impl Collector for DuplicateBuckets {
+    fn collect(&mut self, record: &Record) -> Result<()> {
+        let size = record.entry.size;
+        if size == 0 || record.entry.packed.is_some() {
+            return Ok(());
+        }
+        self.by_size
+            .entry(size)
+            .or_insert_with(|| {
+                self.order.push(size);
+                Vec::new()
+            })
+            .push(record.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entries, Matcher, Pipeline, Ranking};
+
+    #[test]
+    fn one_pass_feeds_every_collector() {
+        let mut source = Entries::new([
+            IndexEntry::new("/a/movie.MKV", 2 << 30),
+            IndexEntry::new("/a/b.mkv", 3 << 20),
+            IndexEntry::new("/a/notes", 100),
+            IndexEntry::new("/a/c.txt", 3 << 20),
+        ]);
+        let mut top = TopN::new(1, Ranking::by_size());
+        let mut by_ext = GroupBy::extension();
+        let mut histogram = Histogram::new(vec![0, 4 << 10, 1 << 20, 1 << 30]);
+        let mut dupes = DuplicateBuckets::new();
+        let mut index = IndexWriter::new(Vec::new());
+
+        Pipeline::new(&Matcher::new(), 1 << 10)
+            .run(
+                &mut source,
+                &mut [
+                    &mut top,
+                    &mut by_ext,
+                    &mut histogram,
+                    &mut dupes,
+                    &mut index,
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(top.into_sorted_vec()[0].path, Path::new("/a/movie.MKV"));
+        let totals = |files, bytes| Totals { files, bytes };
+        assert_eq!(
+            by_ext.largest(1),
+            [("mkv", totals(2, (2 << 30) + (3 << 20)))]
+        );
+        let buckets: Vec<Totals> = histogram.buckets().map(|(_, _, t)| t).collect();
+        assert_eq!(
+            buckets,
+            [
+                totals(0, 0),
+                totals(0, 0),
+                totals(2, 6 << 20),
+                totals(1, 2 << 30)
+            ]
+        );
+        let candidates: Vec<Vec<String>> = dupes
+            .into_candidates()
+            .into_iter()
+            .map(|b| b.into_iter().map(|r| r.entry.path).collect())
+            .collect();
+        assert_eq!(candidates, [["/a/b.mkv", "/a/c.txt"]]);
+        // The index gets every file, including the one under the minimum size.
+        let written = String::from_utf8(index.finish().unwrap()).unwrap();
+        assert_eq!(written.lines().count(), 4);
+    }
+}
@@ -0,0 +1,241 @@
+//! Compress files in place (`big.log` -> `big.log.zst`), removing the original only after the
+//! compressed file has been decompressed and checked against it.
+
+use crate::actions::Skip;
+use crate::checksum::{hash_file, hash_reader};
+use crate::relocate::preserve_metadata;
+use anyhow::Context;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes taken from each of the start, middle and end of a file to estimate compressibility.
+const SAMPLE_CHUNK: u64 = 256 * 1024;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Codec {
+    Zstd,
+    Gzip,
+}
+
+impl Codec {
+    fn extension(self) -> &'static str {
+        match self {
+            Codec::Zstd => "zst",
+            Codec::Gzip => "gz",
+        }
+    }
+
+    /// Compresses all of `input` into `output` and returns `output`.
+    fn encode<W: Write>(self, mut input: impl Read, output: W) -> std::io::Result<W> {
+        match self {
+            Codec::Zstd => {
+                let mut enc = zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+                std::io::copy(&mut input, &mut enc)?;
+                enc.finish()
+            }
+            Codec::Gzip => {
+                let mut enc = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                std::io::copy(&mut input, &mut enc)?;
+                enc.finish()
+            }
+        }
+    }
+
+    fn decoder<'a>(self, input: impl Read + 'a) -> std::io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Codec::Zstd => Box::new(zstd::Decoder::new(input)?),
+            Codec::Gzip => Box::new(flate2::read::GzDecoder::new(input)),
+        })
+    }
+}
+
+pub struct Compressor {
+    codec: Codec,
+    /// Largest acceptable compressed/original size ratio.
+    max_ratio: f64,
+    reclaimed: Cell<u64>,
+}
+
+impl Compressor {
+    pub fn new(codec: Codec, max_ratio: f64) -> Self {
+        Self {
+            codec,
+            max_ratio,
+            reclaimed: Cell::new(0),
+        }
+    }
+
+    /// Total bytes saved by the files compressed so far.
+    pub fn reclaimed(&self) -> u64 {
+        self.reclaimed.get()
+    }
+
+    /// Compresses `path` next to itself and removes it; returns the compressed file.
+    pub fn compress(&self, path: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let original_len = std::fs::metadata(path)?.len();
+        let estimate = self.estimate_ratio(path, original_len)?;
+        if estimate > self.max_ratio {
+            return Err(Skip(format!(
+                "sample compresses to {:.0}% (limit {:.0}%)",
+                estimate * 100.0,
+                self.max_ratio * 100.0
+            ))
+            .into());
+        }
+
+        let mut name = path.file_name().context("No file name")?.to_os_string();
+        name.push(".");
+        name.push(self.codec.extension());
+        let target = path.with_file_name(&name);
+        if target.symlink_metadata().is_ok() {
+            anyhow::bail!("{} already exists", target.display());
+        }
+        name.push(".lff-partial");
+        let partial = path.with_file_name(name);
+
+        let input =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // Everything from creating the partial to moving it into place; any error removes it.
+        let placed = (|| {
+            let output = File::create(&partial)
+                .with_context(|| format!("Failed to create {}", partial.display()))?;
+            let output = self.codec.encode(BufReader::new(input), output)?;
+            output.sync_all()?;
+            drop(output);
+
+            let compressed_len = std::fs::metadata(&partial)?.len();
+            match self.verify(&partial, path) {
+                Err(err) => return Err(err.context("decompression check failed")),
+                Ok(false) => anyhow::bail!("decompressed data does not match the original"),
+                Ok(true) => {}
+            }
+            if compressed_len as f64 > original_len as f64 * self.max_ratio {
+                return Err(Skip(format!(
+                    "only compresses to {:.0}%",
+                    compressed_len as f64 * 100.0 / original_len.max(1) as f64
+                ))
+                .into());
+            }
+
+            preserve_metadata(&std::fs::metadata(path)?, &partial)?;
+            std::fs::rename(&partial, &target)
+                .with_context(|| format!("Failed to move {} into place", partial.display()))?;
+            Ok(compressed_len)
+        })();
+        let compressed_len = match placed {
+            Ok(len) => len,
+            Err(err) => {
+                let _ = std::fs::remove_file(&partial);
+                return Err(err);
+            }
+        };
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+
+        self.reclaimed
+            .set(self.reclaimed.get() + original_len.saturating_sub(compressed_len));
+        Ok(Some(target))
+    }
+
+    fn verify(&self, compressed: &Path, original: &Path) -> anyhow::Result<bool> {
+        let decoder = self
+            .codec
+            .decoder(BufReader::new(File::open(compressed)?))?;
+        Ok(hash_reader(decoder)? == hash_file(original)?)
+    }
+
+    /// Compressed/original ratio of up to three sample chunks, so incompressible media is
+    /// skipped without compressing it whole.
+    fn estimate_ratio(&self, path: &Path, len: u64) -> anyhow::Result<f64> {
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let (offsets, chunk_len) = if len <= 3 * SAMPLE_CHUNK {
+            (vec![0], len)
+        } else {
+            (
+                vec![0, len / 2 - SAMPLE_CHUNK / 2, len - SAMPLE_CHUNK],
+                SAMPLE_CHUNK,
+            )
+        };
+
+        let (mut raw, mut packed) = (0u64, 0u64);
+        for offset in offsets {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut chunk = Vec::new();
+            (&mut file).take(chunk_len).read_to_end(&mut chunk)?;
+            raw += chunk.len() as u64;
+            packed += self.codec.encode(chunk.as_slice(), Vec::new())?.len() as u64;
+        }
+        Ok(if raw == 0 {
+            1.0
+        } else {
+            packed as f64 / raw as f64
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("lff-compress-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        base
+    }
+
+    #[test]
+    fn compresses_text_and_removes_original() {
+        for codec in [Codec::Zstd, Codec::Gzip] {
+            let base = scratch(codec.extension());
+            let file = base.join("app.log");
+            let text = "GET /index.html 200\n".repeat(50_000);
+            std::fs::write(&file, &text).unwrap();
+
+            let compressor = Compressor::new(codec, 0.9);
+            let target = compressor.compress(&file).unwrap().unwrap();
+            assert!(!file.exists());
+            assert_eq!(target, base.join(format!("app.log.{}", codec.extension())));
+
+            let mut roundtrip = String::new();
+            codec
+                .decoder(File::open(&target).unwrap())
+                .unwrap()
+                .read_to_string(&mut roundtrip)
+                .unwrap();
+            assert_eq!(roundtrip, text);
+            assert!(compressor.reclaimed() > text.len() as u64 / 2);
+
+            std::fs::remove_dir_all(&base).unwrap();
+        }
+    }
+
+    #[test]
+    fn skips_incompressible_files() {
+        let base = scratch("random");
+        let file = base.join("noise.bin");
+        // xorshift noise does not compress.
+        let mut x: u64 = 0x9E37_79B9_7F4A_7C15;
+        let noise: Vec<u8> = (0..1_000_000)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        std::fs::write(&file, &noise).unwrap();
+
+        let err = Compressor::new(Codec::Zstd, 0.9)
+            .compress(&file)
+            .unwrap_err();
+        assert!(err.downcast_ref::<Skip>().is_some());
+        assert!(file.exists());
+        assert!(!base.join("noise.bin.zst").exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+//! TOML config file giving defaults for command-line options, and named profiles of them.
+//!
+//! Keys are option names (`min-bytes` or `min_bytes`); `root` is the positional root. Profiles
+//! are tables under `[profiles]`, applied over the top-level defaults with `--profile NAME`:
+//!
+//! ```toml
+//! top = 50
+//! exclude = ["/\\.git/"]
+//!
+//! [profiles.media]
+//! root = "/srv/media"
+//! query = "ext:mkv OR ext:mp4"
+//! report = ["html", "media.html"]
+//! ```
+//!
+//! Options given on the command line win over both. Query fragments used often can be named under
+//! `[queries]` and referenced in `--query` and `--fail-if` as `@name`:
+//!
+//! ```toml
+//! [queries]
+//! video = "ext in (mp4, mkv, mov)"
+//! junk = "@video AND path:/Downloads/"
+//! ```
+
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default)]
+pub struct Config {
+    defaults: toml::Table,
+    profiles: toml::Table,
+    queries: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/largest-file-finder/config.toml`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(config.join("largest-file-finder").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid config file: {}", path.display()))
+    }
+
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut defaults: toml::Table = text.parse()?;
+        let profiles = match defaults.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => anyhow::bail!("`profiles` must be a table"),
+            None => toml::Table::new(),
+        };
+        let mut queries = BTreeMap::new();
+        match defaults.remove("queries") {
+            Some(toml::Value::Table(table)) => {
+                for (name, value) in table {
+                    let toml::Value::String(source) = value else {
+                        anyhow::bail!("Query `{name}` must be a string");
+                    };
+                    queries.insert(name.trim_start_matches('@').to_string(), source);
+                }
+            }
+            Some(_) => anyhow::bail!("`queries` must be a table"),
+            None => {}
+        }
+        Ok(Self {
+            defaults,
+            profiles,
+            queries,
+        })
+    }
+
+    /// Named query fragments, without the leading `@`.
+    pub fn queries(&self) -> &BTreeMap<String, String> {
+        &self.queries
+    }
+
+    /// Command-line arguments for the defaults, with `profile` applied over them, that go before
+    /// the real ones. Settings for which `given(id)` is true are left out, so the command line wins.
+    ///
+    /// A setting must name an option of some command under `root`; those that `leaf` (the
+    /// subcommand being run) does not take are skipped, so one config can serve every subcommand.
+    pub fn args(
+        &self,
+        profile: Option<&str>,
+        root: &clap::Command,
+        leaf: &clap::Command,
+        given: impl Fn(&str) -> bool,
+    ) -> anyhow::Result<Vec<OsString>> {
+        let mut settings = self.defaults.clone();
+        if let Some(name) = profile {
+            match self.profiles.get(name) {
+                Some(toml::Value::Table(table)) => settings.extend(table.clone()),
+                Some(_) => anyhow::bail!("Profile `{name}` must be a table"),
+                None => {
+                    let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                    anyhow::bail!(
+                        "No profile `{name}` in config (defined: {})",
+                        known.join(", ")
+                    );
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        for (key, value) in &settings {
+            let id = key.replace('-', "_");
+            if matches!(id.as_str(), "config" | "profile" | "help") || !takes(root, &id) {
+                anyhow::bail!("Unknown setting `{key}` in config");
+            }
+            let Some(arg) = leaf.get_arguments().find(|a| a.get_id() == id.as_str()) else {
+                continue;
+            };
+            if given(&id) {
+                continue;
+            }
+
+            let values = match value {
+                toml::Value::Boolean(true) => Vec::new(),
+                toml::Value::Boolean(false) => continue,
+                toml::Value::Array(items) => {
+                    items.iter().map(scalar).collect::<anyhow::Result<_>>()?
+                }
+                other => vec![scalar(other)?],
+            };
+            let values_per_flag = arg.get_num_args().map_or(1, |n| n.min_values());
+            match arg.get_long() {
+                None => out.extend(values.into_iter().map(OsString::from)),
+                Some(long) if values.is_empty() => out.push(format!("--{long}").into()),
+                // Options taking several values at once, like `--report html FILE`.
+                Some(long) if values_per_flag > 1 => {
+                    out.push(format!("--{long}").into());
+                    out.extend(values.into_iter().map(OsString::from));
+                }
+                Some(long) => out.extend(values.iter().map(|v| format!("--{long}={v}").into())),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Whether `cmd` or any of its subcommands has an option with this id.
+fn takes(cmd: &clap::Command, id: &str) -> bool {
+    cmd.get_arguments().any(|a| a.get_id() == id) || cmd.get_subcommands().any(|c| takes(c, id))
+}
+
+fn scalar(value: &toml::Value) -> anyhow::Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        other => anyhow::bail!("Unsupported value in config: {other}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    const CONFIG: &str = r#"
+top = 50
+verbose = true
+exclude = ["/\\.git/", "\\.tmp$"]
+
+[profiles.media]
+root = "/srv/media"
+query = "ext:mkv"
+report = ["html", "media.html"]
+verbose = false
+"#;
+
+    #[test]
+    fn profile_overrides_defaults_and_command_line_overrides_both() {
+        let config = Config::parse(CONFIG).unwrap();
+        let mut cmd = crate::cli::Cli::command();
+        cmd.build();
+        let args = config
+            .args(Some("media"), &cmd, &cmd, |id| id == "top")
+            .unwrap();
+        let args: Vec<&str> = args.iter().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(
+            args,
+            vec![
+                "--exclude=/\\.git/",
+                "--exclude=\\.tmp$",
+                "--query=ext:mkv",
+                "--report",
+                "html",
+                "media.html",
+                "/srv/media",
+            ]
+        );
+
+        assert!(config.args(Some("music"), &cmd, &cmd, |_| false).is_err());
+        let bad = Config::parse("colour = true").unwrap();
+        assert!(bad.args(None, &cmd, &cmd, |_| false).is_err());
+
+        // `top` is known, but `restore` does not take it: skipped rather than rejected.
+        let restore = cmd.find_subcommand("restore").unwrap();
+        let args = config.args(None, &cmd, restore, |_| false).unwrap();
+        assert!(args.iter().all(|a| a != "--top=50"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Replace verified duplicate files with hard links or reflinks to a single kept copy.
+//!
+//! Only files on the same filesystem with identical size and SHA-256 are linked. The kept copy is
+//! the highest-ranked one the guard allows; it keeps its owner and permissions and receives the
+//! newest mtime of its set.
+
+use crate::actions::Guard;
+use crate::checksum::{hash_file, same_content};
+use crate::relocate::preserve_metadata;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LinkMode {
+    Hardlink,
+    /// Copy-on-write clone (FICLONE), so the copies can still diverge later
+    Reflink,
+}
+
+/// Identical files with their scanned size, in rank order.
+pub type DuplicateSet = Vec<(PathBuf, u64)>;
+
+/// Groups identical files, each group in the order given. Files that are already hard links of an
+/// earlier item, empty files and files that cannot be read are left out.
+#[cfg(unix)]
+pub fn find_duplicates<'a>(items: impl IntoIterator<Item = (&'a Path, u64)>) -> Vec<DuplicateSet> {
+    use std::collections::HashSet;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut inodes = HashSet::new();
+    let mut by_size: HashMap<(u64, u64), DuplicateSet> = HashMap::new();
+    let mut order = Vec::new();
+    for (path, size) in items {
+        let Ok(md) = std::fs::symlink_metadata(path) else {
+            continue;
+        };
+        if !md.is_file() || size == 0 || !inodes.insert((md.dev(), md.ino())) {
+            continue;
+        }
+        let key = (md.dev(), size);
+        by_size
+            .entry(key)
+            .or_insert_with(|| {
+                order.push(key);
+                Vec::new()
+            })
+            .push((path.to_path_buf(), size));
+    }
+
+    let mut sets = Vec::new();
+    for key in order {
+        let candidates = &by_size[&key];
+        if candidates.len() < 2 {
+            continue;
+        }
+        let mut by_hash: Vec<(Vec<u8>, DuplicateSet)> = Vec::new();
+        for (path, size) in candidates {
+            let Ok(digest) = hash_file(path) else {
+                continue;
+            };
+            match by_hash.iter_mut().find(|(d, _)| *d == digest) {
+                Some((_, set)) => set.push((path.clone(), *size)),
+                None => by_hash.push((digest, vec![(path.clone(), *size)])),
+            }
+        }
+        sets.extend(
+            by_hash
+                .into_iter()
+                .map(|(_, set)| set)
+                .filter(|s| s.len() > 1),
+        );
+    }
+    sets
+}
+
+#[cfg(not(unix))]
+pub fn find_duplicates<'a>(_items: impl IntoIterator<Item = (&'a Path, u64)>) -> Vec<DuplicateSet> {
+    Vec::new()
+}
+
+pub struct Deduper {
+    mode: LinkMode,
+    /// Copies to replace, in order, with their scanned size.
+    copies: Vec<(PathBuf, u64)>,
+    /// Resolved copy path -> (kept copy, newest mtime of the set).
+    plan: HashMap<PathBuf, (PathBuf, Option<SystemTime>)>,
+    sets: usize,
+}
+
+impl Deduper {
+    pub fn new(mode: LinkMode, sets: Vec<DuplicateSet>, guard: &Guard) -> Self {
+        let mut deduper = Self {
+            mode,
+            copies: Vec::new(),
+            plan: HashMap::new(),
+            sets: 0,
+        };
+        for set in sets {
+            let Some(keep) = set.iter().find_map(|(p, _)| guard.check(p).ok()) else {
+                continue;
+            };
+            let newest = set
+                .iter()
+                .filter_map(|(p, _)| std::fs::metadata(p).and_then(|md| md.modified()).ok())
+                .max();
+            deduper.sets += 1;
+            for (path, size) in set {
+                let resolved = guard.check(&path).unwrap_or_else(|_| path.clone());
+                if resolved != keep {
+                    deduper.plan.insert(resolved, (keep.clone(), newest));
+                    deduper.copies.push((path, size));
+                }
+            }
+        }
+        deduper
+    }
+
+    /// Number of duplicate sets that will be linked.
+    pub fn sets(&self) -> usize {
+        self.sets
+    }
+
+    /// The files that will be replaced by links, with their scanned size.
+    pub fn copies(&self) -> impl Iterator<Item = (&Path, u64)> {
+        self.copies.iter().map(|(p, s)| (p.as_path(), *s))
+    }
+
+    pub fn projected_savings(&self) -> u64 {
+        self.copies.iter().map(|(_, s)| s).sum()
+    }
+
+    /// Replaces `path` with a link to its kept copy and returns the kept copy.
+    pub fn dedupe(&self, path: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let (keep, newest) = self
+            .plan
+            .get(path)
+            .with_context(|| format!("{} is not part of a duplicate set", path.display()))?;
+        if !same_content(keep, path)? {
+            anyhow::bail!("no longer identical to {}", keep.display());
+        }
+
+        match self.mode {
+            LinkMode::Hardlink => {
+                let tmp = sibling(path, ".lff-link")?;
+                let _ = std::fs::remove_file(&tmp);
+                std::fs::hard_link(keep, &tmp)
+                    .with_context(|| format!("Failed to link {}", keep.display()))?;
+                replace(&tmp, path)?;
+            }
+            LinkMode::Reflink => {
+                let tmp = sibling(path, ".lff-partial")?;
+                let _ = std::fs::remove_file(&tmp);
+                if let Err(err) = reflink(keep, &tmp) {
+                    let _ = std::fs::remove_file(&tmp);
+                    return Err(err);
+                }
+                let preserved = std::fs::metadata(keep)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|md| preserve_metadata(&md, &tmp));
+                if let Err(err) = preserved {
+                    let _ = std::fs::remove_file(&tmp);
+                    return Err(err);
+                }
+                replace(&tmp, path)?;
+            }
+        }
+
+        if let Some(newest) = newest {
+            let times = std::fs::FileTimes::new().set_modified(*newest);
+            for file in [keep, path] {
+                File::open(file)?.set_times(times)?;
+            }
+        }
+        Ok(Some(keep.clone()))
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> anyhow::Result<PathBuf> {
+    let mut name = path.file_name().context("No file name")?.to_os_string();
+    name.push(suffix);
+    Ok(path.with_file_name(name))
+}
+
+fn replace(tmp: &Path, path: &Path) -> anyhow::Result<()> {
+    if let Err(e) = std::fs::rename(tmp, path) {
+        let _ = std::fs::remove_file(tmp);
+        return Err(e).with_context(|| format!("Failed to replace {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Creates `dest` as a copy-on-write clone of `src`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn reflink(src: &Path, dest: &Path) -> anyhow::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let input = File::open(src).with_context(|| format!("Failed to open {}", src.display()))?;
+    let output = File::options()
+        .write(true)
+        .create_new(true)
+        .open(dest)
+        .with_context(|| format!("Failed to create {}", dest.display()))?;
+    // SAFETY: both descriptors are open for the duration of the call; FICLONE takes the source
+    // descriptor by value.
+    let rc = unsafe { libc::ioctl(output.as_raw_fd(), libc::FICLONE, input.as_raw_fd()) };
+    if rc != 0 {
+        let err = std::io::Error::last_os_error();
+        anyhow::bail!("reflink failed ({err}); the filesystem may not support it, try hardlink");
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn reflink(_src: &Path, _dest: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("reflinks are only supported on Linux")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn hardlinks_identical_files_and_keeps_newest_mtime() {
+        let base = std::env::temp_dir().join(format!("lff-dedupe-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        let base = base.canonicalize().unwrap();
+        let (a, b, c) = (base.join("a.iso"), base.join("b.iso"), base.join("c.iso"));
+        std::fs::write(&a, b"same bytes").unwrap();
+        std::fs::write(&b, b"same bytes").unwrap();
+        std::fs::write(&c, b"diff bytes").unwrap();
+        let old = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&a)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let newest = std::fs::metadata(&b).unwrap().modified().unwrap();
+
+        let items = [(a.as_path(), 10), (b.as_path(), 10), (c.as_path(), 10)];
+        let sets = find_duplicates(items);
+        assert_eq!(sets, vec![vec![(a.clone(), 10), (b.clone(), 10)]]);
+
+        let guard = Guard::new(&base, &[]).unwrap();
+        let deduper = Deduper::new(LinkMode::Hardlink, sets, &guard);
+        assert_eq!(deduper.projected_savings(), 10);
+        assert_eq!(deduper.dedupe(&b).unwrap(), Some(a.clone()));
+
+        let (ma, mb) = (
+            std::fs::metadata(&a).unwrap(),
+            std::fs::metadata(&b).unwrap(),
+        );
+        assert_eq!(ma.ino(), mb.ino());
+        assert_eq!(ma.modified().unwrap(), newest);
+        // Already linked: nothing left to do.
+        assert!(find_duplicates([(a.as_path(), 10), (b.as_path(), 10)]).is_empty());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! Size changes between two sets of files, e.g. two indexes of the same tree.
+
+use humansize::{format_size, BINARY};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub path: String,
+    /// Size before; `None` when the file is new
+    pub old: Option<u64>,
+    /// Size after; `None` when the file is gone
+    pub new: Option<u64>,
+}
+
+impl Change {
+    pub fn delta(&self) -> i128 {
+        i128::from(self.new.unwrap_or(0)) - i128::from(self.old.unwrap_or(0))
+    }
+
+    pub fn format_delta(&self) -> String {
+        format_delta(self.delta())
+    }
+
+    /// `(new)`, `(removed)` or `(1 GiB -> 1.2 GiB)`.
+    pub fn describe(&self) -> String {
+        match (self.old, self.new) {
+            (None, _) => "new".to_string(),
+            (_, None) => "removed".to_string(),
+            (Some(old), Some(new)) => {
+                format!(
+                    "{} -> {}",
+                    format_size(old, BINARY),
+                    format_size(new, BINARY)
+                )
+            }
+        }
+    }
+}
+
+/// `+1.2 GiB` or `-300 MiB`.
+pub fn format_delta(delta: i128) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{sign}{}", format_size(delta.unsigned_abs() as u64, BINARY))
+}
+
+/// Files added, removed or resized between `old` and `new`, largest change first (ties by path).
+pub fn changes(
+    old: impl IntoIterator<Item = (String, u64)>,
+    new: impl IntoIterator<Item = (String, u64)>,
+) -> Vec<Change> {
+    let mut before: HashMap<String, u64> = old.into_iter().collect();
+    let mut changes = Vec::new();
+    for (path, size) in new {
+        match before.remove(&path) {
+            Some(old) if old == size => {}
+            old => changes.push(Change {
+                path,
+                old,
+                new: Some(size),
+            }),
+        }
+    }
+    changes.extend(before.into_iter().map(|(path, size)| Change {
+        path,
+        old: Some(size),
+        new: None,
+    }));
+    changes.sort_by(|a, b| {
+        b.delta()
+            .abs()
+            .cmp(&a.delta().abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(list: &[(&str, u64)]) -> Vec<(String, u64)> {
+        list.iter().map(|(p, s)| (p.to_string(), *s)).collect()
+    }
+
+    #[test]
+    fn orders_changes_by_size_of_change() {
+        let old = files(&[("/a", 100), ("/b", 50), ("/gone", 30), ("/same", 7)]);
+        let new = files(&[("/a", 40), ("/b", 250), ("/new", 60), ("/same", 7)]);
+        let got: Vec<(String, i128)> = changes(old, new)
+            .iter()
+            .map(|c| (c.path.clone(), c.delta()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("/b".to_string(), 200),
+                ("/a".to_string(), -60),
+                ("/new".to_string(), 60),
+                ("/gone".to_string(), -30),
+            ]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Reads the files tracked or staged in a git repository straight from its index file
+//! (`.git/index`, format versions 2 to 4), without running git.
+
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+/// Mode bits of entries that are not regular files in the work tree.
+const MODE_TYPE_MASK: u32 = 0o170000;
+const MODE_SYMLINK: u32 = 0o120000;
+const MODE_GITLINK: u32 = 0o160000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedFile {
+    /// Relative to the work tree, `/`-separated
+    pub path: String,
+    /// Size recorded when the file was staged, truncated to 32 bits by git
+    pub size: u32,
+    pub mtime: i64,
+}
+
+#[derive(Debug)]
+pub struct Repo {
+    pub work_tree: PathBuf,
+    pub index: PathBuf,
+}
+
+impl Repo {
+    /// Finds the repository containing `start`, following `.git` files (worktrees, submodules).
+    /// `GIT_INDEX_FILE` is honored, as git sets it for hooks run by `git commit <paths>`.
+    pub fn discover(start: &Path) -> anyhow::Result<Self> {
+        let start = start
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", start.display()))?;
+        let work_tree = start
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .with_context(|| format!("Not inside a git work tree: {}", start.display()))?
+            .to_path_buf();
+
+        let dot_git = work_tree.join(".git");
+        let git_dir = if dot_git.is_file() {
+            let text = std::fs::read_to_string(&dot_git)
+                .with_context(|| format!("Failed to read {}", dot_git.display()))?;
+            let target = text
+                .trim()
+                .strip_prefix("gitdir:")
+                .with_context(|| format!("Unrecognized .git file: {}", dot_git.display()))?;
+            work_tree.join(target.trim())
+        } else {
+            dot_git
+        };
+
+        // Like git, a relative GIT_INDEX_FILE is taken from the current directory.
+        let index = match std::env::var_os("GIT_INDEX_FILE") {
+            Some(file) => std::path::absolute(&file)
+                .with_context(|| format!("Failed to resolve GIT_INDEX_FILE {}", file.display()))?,
+            None => git_dir.join("index"),
+        };
+        Ok(Self { work_tree, index })
+    }
+
+    /// Regular files in the index, each once even when it has merge conflicts.
+    pub fn files(&self) -> anyhow::Result<Vec<TrackedFile>> {
+        let data = std::fs::read(&self.index)
+            .with_context(|| format!("Failed to read git index {}", self.index.display()))?;
+        parse_index(&data).with_context(|| format!("Invalid git index {}", self.index.display()))
+    }
+}
+
+fn parse_index(data: &[u8]) -> anyhow::Result<Vec<TrackedFile>> {
+    let mut r = Reader { data, pos: 0 };
+    if r.take(4)? != b"DIRC" {
+        anyhow::bail!("missing DIRC signature");
+    }
+    let version = r.u32()?;
+    if !(2..=4).contains(&version) {
+        anyhow::bail!("unsupported index version {version}");
+    }
+    let count = r.u32()?;
+
+    let mut entries: Vec<TrackedFile> = Vec::with_capacity(count as usize);
+    let mut previous: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = r.pos;
+        r.take(8)?; // ctime
+        let mtime = i64::from(r.u32()?);
+        r.take(4 + 4 + 4)?; // mtime nanoseconds, dev, ino
+        let mode = r.u32()?;
+        r.take(4 + 4)?; // uid, gid
+        let size = r.u32()?;
+        r.take(20)?; // object id (SHA-1)
+        let flags = r.u16()?;
+        if version >= 3 && flags & 0x4000 != 0 {
+            r.take(2)?; // extended flags
+        }
+
+        let path = if version == 4 {
+            // The path is stored as the number of bytes to drop from the previous path, followed by
+            // the NUL-terminated suffix to append.
+            let strip = r.varint()? as usize;
+            let keep = previous
+                .len()
+                .checked_sub(strip)
+                .context("path prefix longer than the previous path")?;
+            let mut path = previous[..keep].to_vec();
+            path.extend_from_slice(r.until_nul()?);
+            path
+        } else {
+            let path = r.until_nul()?.to_vec();
+            // Entries are NUL-padded to a multiple of 8 bytes; one NUL was read with the path.
+            let len = r.pos - start;
+            r.take((8 - len % 8) % 8)?;
+            path
+        };
+
+        let kind = mode & MODE_TYPE_MASK;
+        if kind != MODE_SYMLINK && kind != MODE_GITLINK {
+            let path_str = String::from_utf8_lossy(&path).into_owned();
+            // Conflicting stages of a path are stored next to each other.
+            if entries.last().is_none_or(|e| e.path != path_str) {
+                entries.push(TrackedFile {
+                    path: path_str,
+                    size,
+                    mtime,
+                });
+            }
+        }
+        previous = path;
+    }
+    Ok(entries)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .context("unexpected end of index")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// Git's offset varint, as used by index v4 and pack files.
+    fn varint(&mut self) -> anyhow::Result<u64> {
+        let mut byte = self.take(1)?[0];
+        let mut value = u64::from(byte & 0x7f);
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = ((value + 1) << 7) | u64::from(byte & 0x7f);
+        }
+        Ok(value)
+    }
+
+    fn until_nul(&mut self) -> anyhow::Result<&'a [u8]> {
+        let rest = &self.data[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .context("unterminated path")?;
+        self.pos += len + 1;
+        Ok(&rest[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(version: u32, mode: u32, size: u32, name: &[u8]) -> Vec<u8> {
+        let mut e = Vec::new();
+        e.extend_from_slice(&[0; 8]);
+        e.extend_from_slice(&1_700_000_000u32.to_be_bytes());
+        e.extend_from_slice(&[0; 12]);
+        e.extend_from_slice(&mode.to_be_bytes());
+        e.extend_from_slice(&[0; 8]);
+        e.extend_from_slice(&size.to_be_bytes());
+        e.extend_from_slice(&[0xab; 20]);
+        e.extend_from_slice(&(name.len().min(0xfff) as u16).to_be_bytes());
+        e.extend_from_slice(name);
+        e.push(0);
+        if version < 4 {
+            while e.len() % 8 != 0 {
+                e.push(0);
+            }
+        }
+        e
+    }
+
+    fn index(version: u32, entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"DIRC".to_vec();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for e in entries {
+            data.extend_from_slice(e);
+        }
+        data
+    }
+
+    #[test]
+    fn parses_v2_entries_skipping_symlinks_and_submodules() {
+        let data = index(
+            2,
+            &[
+                entry(2, 0o100644, 10, b"README.md"),
+                entry(2, 0o120000, 5, b"link"),
+                entry(2, 0o100755, 2_000_000, b"tools/big.bin"),
+                entry(2, 0o160000, 0, b"vendor/sub"),
+            ],
+        );
+        let files = parse_index(&data).unwrap();
+        let got: Vec<(&str, u32)> = files.iter().map(|e| (e.path.as_str(), e.size)).collect();
+        assert_eq!(got, vec![("README.md", 10), ("tools/big.bin", 2_000_000)]);
+        assert_eq!(files[0].mtime, 1_700_000_000);
+    }
+
+    #[test]
+    fn parses_v4_prefix_compressed_paths() {
+        // "assets/a.png", then "assets/b.png" stored as: drop 5 bytes ("a.png"), append "b.png".
+        let mut first = entry(4, 0o100644, 1, b"assets/a.png");
+        let mut second = entry(4, 0o100644, 2, b"b.png");
+        let name_at = first.len() - b"assets/a.png\0".len();
+        first.insert(name_at, 0);
+        let name_at = second.len() - b"b.png\0".len();
+        second.insert(name_at, 5);
+        let files = parse_index(&index(4, &[first, second])).unwrap();
+        let paths: Vec<&str> = files.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["assets/a.png", "assets/b.png"]);
+    }
+}
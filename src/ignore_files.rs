@@ -0,0 +1,131 @@
+//! `.gitignore`-style ignore files, applied while walking so ignored directories are never entered.
+
+use crate::vfs::{FileKind, Filesystem};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Read in every directory, in increasing precedence.
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", ".lffignore"];
+
+/// The ignore rules of the directories leading to the entry being walked.
+///
+/// Entries must be offered in depth-first walk order, as the scan does: a directory's rules are
+/// pushed when it is admitted and dropped once the walk leaves it.
+#[derive(Default)]
+pub struct IgnoreStack {
+    frames: Vec<(PathBuf, Gitignore)>,
+}
+
+impl IgnoreStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false when `path` is ignored and should be skipped, including everything below it.
+    pub fn admit<F: Filesystem + ?Sized>(&mut self, fs: &F, path: &Path, is_dir: bool) -> bool {
+        while let Some((dir, _)) = self.frames.last() {
+            if path.parent().is_some_and(|p| p.starts_with(dir)) {
+                break;
+            }
+            self.frames.pop();
+        }
+
+        // The deepest directory with an opinion wins, like git.
+        for (_, rules) in self.frames.iter().rev() {
+            match rules.matched(path, is_dir) {
+                Match::Ignore(_) => return false,
+                Match::Whitelist(_) => break,
+                Match::None => {}
+            }
+        }
+
+        if is_dir {
+            if let Some(rules) = load(fs, path) {
+                self.frames.push((path.to_path_buf(), rules));
+            }
+        }
+        true
+    }
+}
+
+fn load<F: Filesystem + ?Sized>(fs: &F, dir: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for name in IGNORE_FILES {
+        let file = dir.join(name);
+        if !fs.metadata(&file).is_ok_and(|md| md.kind == FileKind::File) {
+            continue;
+        }
+        found = true;
+        let mut text = String::new();
+        if let Err(err) = fs.open(&file).and_then(|mut f| f.read_to_string(&mut text)) {
+            eprintln!("Warning: {}: {err}", file.display());
+            continue;
+        }
+        // Invalid lines are reported but do not discard the rest of the file.
+        for line in text.lines() {
+            if let Err(err) = builder.add_line(Some(file.clone()), line) {
+                eprintln!("Warning: {err}");
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+    match builder.build() {
+        Ok(rules) => Some(rules),
+        Err(err) => {
+            eprintln!("Warning: ignoring rules in {}: {err}", dir.display());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::RealFs;
+    use walkdir::WalkDir;
+
+    #[test]
+    fn prunes_ignored_dirs_and_honours_negation_and_anchors() {
+        let base = std::env::temp_dir().join(format!("lff-ignore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        for dir in ["build/out", "src/build", "logs", "sub"] {
+            std::fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        for file in [
+            "build/out/a.o",
+            "src/build/keep.rs",
+            "logs/a.log",
+            "logs/important.log",
+            "sub/x.tmp",
+            "sub/y.txt",
+        ] {
+            std::fs::write(base.join(file), b"x").unwrap();
+        }
+        std::fs::write(base.join(".gitignore"), "/build/\n*.log\n").unwrap();
+        std::fs::write(base.join(".lffignore"), "!important.log\n").unwrap();
+        std::fs::write(base.join("sub/.ignore"), "*.tmp\n").unwrap();
+
+        let mut stack = IgnoreStack::new();
+        let mut seen: Vec<String> = WalkDir::new(&base)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| stack.admit(&RealFs, e.path(), e.file_type().is_dir()))
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().strip_prefix(&base).unwrap().display().to_string())
+            .filter(|p| !p.ends_with("ignore"))
+            .collect();
+        seen.sort();
+        assert_eq!(
+            seen,
+            vec!["logs/important.log", "src/build/keep.rs", "sub/y.txt"]
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,563 @@
+//! Analysis of container images stored as an OCI image layout directory or a `docker save`
+//! tarball.
+//!
+//! Every layer tar is read in order and its files are attributed to that layer. A file that a
+//! later layer deletes (`.wh.NAME`, or an opaque `.wh..wh..opq` directory) or overwrites still
+//! takes space in the image, so it is marked as wasted.
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+/// Tarball members up to this size are kept in memory while looking for the manifest.
+const SMALL_MEMBER: u64 = 4 << 20;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Waste {
+    /// Removed by a whiteout in this layer
+    Deleted(usize),
+    /// Overwritten by the same path in this layer
+    Replaced(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerFile {
+    pub layer: usize,
+    /// Path inside the image, without a leading `/`
+    pub path: String,
+    pub size: u64,
+    pub mtime: Option<i64>,
+    pub wasted: Option<Waste>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Layer {
+    /// Digest (OCI) or layer directory name (legacy `docker save`)
+    pub id: String,
+    /// Size of the layer blob as stored, possibly compressed
+    pub blob_size: u64,
+    /// Total size of the files the layer adds
+    pub size: u64,
+    pub files: u64,
+    /// Bytes of this layer's files that later layers delete or overwrite
+    pub wasted: u64,
+}
+
+pub struct Image {
+    pub layers: Vec<Layer>,
+    pub files: Vec<LayerFile>,
+}
+
+impl Image {
+    /// Virtual path of a file, e.g. `app.tar!/layer-2/usr/lib/libfoo.so`.
+    pub fn virtual_path(source: &Path, file: &LayerFile) -> String {
+        format!(
+            "{}{}layer-{}/{}",
+            source.display(),
+            largest_file_finder::archive::SEPARATOR,
+            file.layer + 1,
+            file.path
+        )
+    }
+}
+
+/// One entry of a layer tar, before whiteouts are applied.
+#[derive(Debug, Clone)]
+enum Change {
+    File {
+        path: String,
+        size: u64,
+        mtime: Option<i64>,
+    },
+    /// Deletes this path (and everything under it) from lower layers
+    Whiteout(String),
+    /// Hides everything lower layers put in this directory
+    Opaque(String),
+}
+
+/// Reads an OCI layout directory or an image tarball and attributes files to layers.
+pub fn analyze(path: &Path) -> anyhow::Result<Image> {
+    let mut source = if path.is_dir() {
+        Source::Dir(path.to_path_buf())
+    } else {
+        Source::tarball(path)?
+    };
+    let layer_names = source.layer_names()?;
+    let mut layers: Vec<Layer> = layer_names
+        .iter()
+        .map(|(id, _)| Layer {
+            id: id.clone(),
+            ..Layer::default()
+        })
+        .collect();
+    let names: Vec<String> = layer_names.into_iter().map(|(_, name)| name).collect();
+    let mut changes: Vec<Vec<Change>> = vec![Vec::new(); names.len()];
+    source.read_layers(&names, |idx, blob_size, reader| {
+        layers[idx].blob_size = blob_size;
+        changes[idx] = read_layer(reader)?;
+        Ok(())
+    })?;
+
+    let files = apply_layers(changes);
+    for file in &files {
+        let layer = &mut layers[file.layer];
+        layer.files += 1;
+        layer.size += file.size;
+        if file.wasted.is_some() {
+            layer.wasted += file.size;
+        }
+    }
+    Ok(Image { layers, files })
+}
+
+/// Replays the layers in order; returns every file with its fate.
+fn apply_layers(layers: Vec<Vec<Change>>) -> Vec<LayerFile> {
+    let mut files: Vec<LayerFile> = Vec::new();
+    // Path -> index into `files` of the copy currently visible.
+    let mut visible: BTreeMap<String, usize> = BTreeMap::new();
+
+    for (layer, changes) in layers.into_iter().enumerate() {
+        // Whiteouts only affect lower layers, whatever their position in this layer's tar.
+        for change in &changes {
+            let hidden: Vec<String> = match change {
+                Change::Whiteout(path) => {
+                    let under = format!("{path}/");
+                    visible
+                        .keys()
+                        .filter(|p| *p == path || p.starts_with(&under))
+                        .cloned()
+                        .collect()
+                }
+                Change::Opaque(dir) => {
+                    let under = if dir.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{dir}/")
+                    };
+                    visible
+                        .range(under.clone()..)
+                        .take_while(|(p, _)| p.starts_with(&under))
+                        .map(|(p, _)| p.clone())
+                        .collect()
+                }
+                Change::File { .. } => continue,
+            };
+            for path in hidden {
+                if let Some(idx) = visible.remove(&path) {
+                    files[idx].wasted = Some(Waste::Deleted(layer));
+                }
+            }
+        }
+
+        for change in changes {
+            if let Change::File { path, size, mtime } = change {
+                if let Some(idx) = visible.insert(path.clone(), files.len()) {
+                    files[idx].wasted = Some(Waste::Replaced(layer));
+                }
+                files.push(LayerFile {
+                    layer,
+                    path,
+                    size,
+                    mtime,
+                    wasted: None,
+                });
+            }
+        }
+    }
+    files
+}
+
+/// Lists a layer tar, which may be uncompressed, gzip or zstd.
+fn read_layer(reader: impl Read) -> anyhow::Result<Vec<Change>> {
+    let mut buffered = BufReader::new(reader);
+    let magic = buffered.fill_buf()?;
+    let stream: Box<dyn Read + '_> = if magic.starts_with(&[0x1f, 0x8b]) {
+        Box::new(flate2::bufread::GzDecoder::new(buffered))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::Decoder::with_buffer(buffered)?)
+    } else {
+        Box::new(buffered)
+    };
+
+    let mut changes = Vec::new();
+    for entry in tar::Archive::new(stream).entries()? {
+        let entry = entry?;
+        let raw = entry.path()?.to_string_lossy().into_owned();
+        let path = raw
+            .trim_start_matches("./")
+            .trim_start_matches('/')
+            .trim_end_matches('/')
+            .to_string();
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (dir.to_string(), name),
+            None => (String::new(), path.as_str()),
+        };
+        if name == OPAQUE_MARKER {
+            changes.push(Change::Opaque(dir));
+        } else if let Some(target) = name.strip_prefix(WHITEOUT_PREFIX) {
+            let target = if dir.is_empty() {
+                target.to_string()
+            } else {
+                format!("{dir}/{target}")
+            };
+            changes.push(Change::Whiteout(target));
+        } else if entry.header().entry_type().is_file() {
+            changes.push(Change::File {
+                size: entry.size(),
+                mtime: entry.header().mtime().ok().map(|t| t as i64),
+                path,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+#[derive(Deserialize)]
+struct DockerManifest {
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+    #[serde(rename = "RepoTags", default)]
+    repo_tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct OciDescriptor {
+    #[serde(rename = "mediaType", default)]
+    media_type: String,
+    digest: String,
+}
+
+/// An OCI image index or image manifest; whichever fields apply are present.
+#[derive(Deserialize)]
+struct OciDocument {
+    #[serde(default)]
+    manifests: Vec<OciDescriptor>,
+    #[serde(default)]
+    layers: Vec<OciDescriptor>,
+}
+
+enum Source {
+    Dir(PathBuf),
+    Tar {
+        path: PathBuf,
+        /// Small members, read in the first pass
+        small: HashMap<String, Vec<u8>>,
+        /// Symlink members (newer `docker save` links `ID/layer.tar` into `blobs/`)
+        links: HashMap<String, String>,
+    },
+}
+
+impl Source {
+    fn tarball(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut small = HashMap::new();
+        let mut links = HashMap::new();
+        let mut archive = tar::Archive::new(BufReader::new(file));
+        for entry in archive
+            .entries()
+            .with_context(|| format!("Failed to read {}", path.display()))?
+        {
+            let mut entry = entry?;
+            let name = normalize(&entry.path()?.to_string_lossy());
+            if entry.header().entry_type().is_symlink() {
+                if let Some(target) = entry.link_name()? {
+                    let dir = name.rsplit_once('/').map_or("", |(d, _)| d);
+                    let target = resolve(dir, &target.to_string_lossy());
+                    links.insert(name, target);
+                }
+            } else if entry.header().entry_type().is_file() && entry.size() <= SMALL_MEMBER {
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data)?;
+                small.insert(name, data);
+            }
+        }
+        Ok(Source::Tar {
+            path: path.to_path_buf(),
+            small,
+            links,
+        })
+    }
+
+    fn read_small(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self {
+            Source::Dir(dir) => match std::fs::read(dir.join(name)) {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).with_context(|| format!("Failed to read {name}")),
+            },
+            Source::Tar { small, .. } => Ok(small.get(&self.resolve_link(name)).cloned()),
+        }
+    }
+
+    fn resolve_link(&self, name: &str) -> String {
+        let mut name = normalize(name);
+        if let Source::Tar { links, .. } = self {
+            // Bounded, in case of a link cycle.
+            for _ in 0..8 {
+                match links.get(&name) {
+                    Some(target) => name = target.clone(),
+                    None => break,
+                }
+            }
+        }
+        name
+    }
+
+    /// `(layer id, member name)` of each layer, lowest first.
+    fn layer_names(&self) -> anyhow::Result<Vec<(String, String)>> {
+        if let Some(data) = self.read_small("manifest.json")? {
+            let manifests: Vec<DockerManifest> =
+                serde_json::from_slice(&data).context("Invalid manifest.json")?;
+            let Some(first) = manifests.first() else {
+                anyhow::bail!("manifest.json lists no images");
+            };
+            if manifests.len() > 1 {
+                let tags = first.repo_tags.as_deref().unwrap_or_default().join(", ");
+                eprintln!(
+                    "Warning: {} images in manifest.json; analyzing the first ({tags})",
+                    manifests.len()
+                );
+            }
+            return Ok(first
+                .layers
+                .iter()
+                .map(|name| {
+                    let resolved = self.resolve_link(name);
+                    let id = resolved
+                        .strip_prefix("blobs/sha256/")
+                        .map(|hex| format!("sha256:{hex}"))
+                        .unwrap_or_else(|| name.trim_end_matches("/layer.tar").to_string());
+                    (id, name.clone())
+                })
+                .collect());
+        }
+
+        let Some(data) = self.read_small("index.json")? else {
+            anyhow::bail!(
+                "Not an OCI image layout or docker save tarball (no index.json or manifest.json)"
+            );
+        };
+        let mut doc: OciDocument = serde_json::from_slice(&data).context("Invalid index.json")?;
+        // Follow nested indexes (multi-platform images) down to the first image manifest.
+        for _ in 0..8 {
+            if !doc.layers.is_empty() {
+                return Ok(doc
+                    .layers
+                    .iter()
+                    .map(|l| (l.digest.clone(), blob_name(&l.digest)))
+                    .collect());
+            }
+            let Some(next) = doc.manifests.first() else {
+                break;
+            };
+            if doc.manifests.len() > 1 {
+                eprintln!(
+                    "Warning: {} manifests in the image index; analyzing the first ({})",
+                    doc.manifests.len(),
+                    next.media_type
+                );
+            }
+            let name = blob_name(&next.digest);
+            let data = self
+                .read_small(&name)?
+                .with_context(|| format!("Missing blob {name}"))?;
+            doc = serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid manifest {name}"))?;
+        }
+        anyhow::bail!("No image manifest with layers found")
+    }
+
+    /// Calls `visit(layer index, blob size, reader)` for each named layer, in no particular order.
+    fn read_layers(
+        &mut self,
+        names: &[String],
+        mut visit: impl FnMut(usize, u64, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let wanted: Vec<String> = names.iter().map(|n| self.resolve_link(n)).collect();
+        match self {
+            Source::Dir(dir) => {
+                for (idx, name) in wanted.iter().enumerate() {
+                    let path = dir.join(name);
+                    let file = File::open(&path)
+                        .with_context(|| format!("Failed to open layer {}", path.display()))?;
+                    let blob_size = file.metadata()?.len();
+                    visit(idx, blob_size, &mut BufReader::new(file))
+                        .with_context(|| format!("Failed to read layer {name}"))?;
+                }
+            }
+            Source::Tar { path, small, .. } => {
+                let mut remaining = Vec::new();
+                for (idx, name) in wanted.iter().enumerate() {
+                    match small.get(name) {
+                        Some(data) => visit(idx, data.len() as u64, &mut data.as_slice())
+                            .with_context(|| format!("Failed to read layer {name}"))?,
+                        None => remaining.push(idx),
+                    }
+                }
+                if remaining.is_empty() {
+                    return Ok(());
+                }
+                // Second pass for the layers too big to keep in memory.
+                let file = File::open(&*path)?;
+                let mut archive = tar::Archive::new(BufReader::new(file));
+                for entry in archive.entries()? {
+                    let mut entry = entry?;
+                    let name = normalize(&entry.path()?.to_string_lossy());
+                    let size = entry.size();
+                    let (found, rest): (Vec<usize>, Vec<usize>) =
+                        remaining.iter().partition(|&&i| wanted[i] == name);
+                    remaining = rest;
+                    match found.as_slice() {
+                        [] => {}
+                        [idx] => visit(*idx, size, &mut entry)
+                            .with_context(|| format!("Failed to read layer {name}"))?,
+                        // The same layer listed twice; a stream can only be read once.
+                        _ => {
+                            let mut data = Vec::new();
+                            entry.read_to_end(&mut data)?;
+                            for idx in found {
+                                visit(idx, size, &mut data.as_slice())
+                                    .with_context(|| format!("Failed to read layer {name}"))?;
+                            }
+                        }
+                    }
+                }
+                if let Some(&idx) = remaining.first() {
+                    anyhow::bail!("Layer {} is missing from {}", wanted[idx], path.display());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn blob_name(digest: &str) -> String {
+    match digest.split_once(':') {
+        Some((algo, hex)) => format!("blobs/{algo}/{hex}"),
+        None => format!("blobs/{digest}"),
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// Resolves a relative symlink target against the directory containing the link.
+fn resolve(dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = if target.starts_with('/') {
+        Vec::new()
+    } else {
+        dir.split('/').filter(|p| !p.is_empty()).collect()
+    };
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            p => parts.push(p),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> Change {
+        Change::File {
+            path: path.to_string(),
+            size,
+            mtime: None,
+        }
+    }
+
+    #[test]
+    fn whiteouts_and_overwrites_mark_lower_files_wasted() {
+        let files = apply_layers(vec![
+            vec![
+                file("app/data.bin", 100),
+                file("app/cache/a", 10),
+                file("etc/conf", 1),
+            ],
+            vec![
+                Change::Whiteout("app/data.bin".to_string()),
+                Change::Opaque("app/cache".to_string()),
+                file("app/cache/b", 5),
+                file("etc/conf", 2),
+            ],
+        ]);
+        let fate: Vec<(&str, usize, Option<Waste>)> = files
+            .iter()
+            .map(|f| (f.path.as_str(), f.layer, f.wasted))
+            .collect();
+        assert_eq!(
+            fate,
+            vec![
+                ("app/data.bin", 0, Some(Waste::Deleted(1))),
+                ("app/cache/a", 0, Some(Waste::Deleted(1))),
+                ("etc/conf", 0, Some(Waste::Replaced(1))),
+                ("app/cache/b", 1, None),
+                ("etc/conf", 1, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_docker_save_tarball() {
+        let base = std::env::temp_dir().join(format!("lff-image-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        let path = base.join("image.tar");
+
+        let layer = |entries: &[(&str, usize)]| {
+            let mut builder = tar::Builder::new(Vec::new());
+            for (name, len) in entries {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(*len as u64);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, name, vec![0u8; *len].as_slice())
+                    .unwrap();
+            }
+            builder.into_inner().unwrap()
+        };
+        let manifest =
+            br#"[{"RepoTags":["app:latest"],"Layers":["aaa/layer.tar","bbb/layer.tar"]}]"#;
+        let mut outer = tar::Builder::new(File::create(&path).unwrap());
+        for (name, data) in [
+            ("aaa/layer.tar", layer(&[("big.iso", 3000), ("keep", 1)])),
+            ("bbb/layer.tar", layer(&[(".wh.big.iso", 0)])),
+            ("manifest.json", manifest.to_vec()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            outer
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        outer.finish().unwrap();
+
+        let image = analyze(&path).unwrap();
+        assert_eq!(image.layers.len(), 2);
+        assert_eq!(image.layers[0].id, "aaa");
+        assert_eq!(image.layers[0].size, 3001);
+        assert_eq!(image.layers[0].wasted, 3000);
+        assert_eq!(image.files[0].wasted, Some(Waste::Deleted(1)));
+        assert_eq!(
+            Image::virtual_path(&path, &image.files[0]),
+            format!("{}!/layer-1/big.iso", path.display())
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
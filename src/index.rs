@@ -0,0 +1,216 @@
+//! The JSONL index: one [`IndexEntry`] per line, written by a scan and read back instead of one.
+
+use crate::source::{EntrySource, Record};
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+
+/// A file, as recorded in an index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub path: String,
+    pub size: u64,
+    /// Modification time, seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    /// Access time, seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atime: Option<i64>,
+    /// Compressed size inside the archive, for `ARCHIVE!/MEMBER` entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packed: Option<u64>,
+}
+
+impl IndexEntry {
+    /// A file of `size` bytes, with no times recorded.
+    pub fn new(path: impl Into<String>, size: u64) -> Self {
+        Self {
+            path: path.into(),
+            size,
+            mtime: None,
+            atime: None,
+            packed: None,
+        }
+    }
+}
+
+/// Iterates over the entries of an index. Lines that cannot be read or parsed are skipped and
+/// counted, so a truncated or partly corrupt index still yields everything else.
+pub struct IndexReader<R = BufReader<File>> {
+    lines: Lines<R>,
+    read_lines: u64,
+    parsed: u64,
+    skipped: u64,
+}
+
+impl IndexReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .map_err(|e| Error::io(format!("Failed to open index file: {}", path.display()), e))?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> IndexReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            read_lines: 0,
+            parsed: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Lines read so far, including blank and skipped ones.
+    pub fn read_lines(&self) -> u64 {
+        self.read_lines
+    }
+
+    /// Entries returned so far.
+    pub fn parsed(&self) -> u64 {
+        self.parsed
+    }
+
+    /// Lines that could not be read or parsed so far.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+impl<R: BufRead> Iterator for IndexReader<R> {
+    type Item = IndexEntry;
+
+    fn next(&mut self) -> Option<IndexEntry> {
+        for line_res in self.lines.by_ref() {
+            self.read_lines += 1;
+            let Ok(line) = line_res else {
+                self.skipped += 1;
+                continue;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(rec) => {
+                    self.parsed += 1;
+                    return Some(rec);
+                }
+                Err(_) => self.skipped += 1,
+            }
+        }
+        None
+    }
+}
+
+/// Writes entries as JSONL. Call [`IndexWriter::finish`] to flush.
+pub struct IndexWriter<W: Write = BufWriter<File>> {
+    out: W,
+}
+
+impl IndexWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).map_err(|e| {
+            Error::io(
+                format!("Failed to create index file: {}", path.display()),
+                e,
+            )
+        })?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> IndexWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn write(&mut self, entry: &IndexEntry) -> Result<()> {
+        serde_json::to_writer(self.out.by_ref(), entry)
+            .map_err(|e| Error::io("Failed to write JSON record to index", e))?;
+        self.out
+            .write_all(b"\n")
+            .map_err(|e| Error::io("Failed to write newline to index", e))
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.out
+            .flush()
+            .map_err(|e| Error::io("Failed to flush index file", e))?;
+        Ok(self.out)
+    }
+}
+
+impl<R: BufRead> EntrySource for IndexReader<R> {
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        Ok(self.next().map(Record::from))
+    }
+
+    fn progress(&self) -> String {
+        format!(
+            "Index lines: {}, parsed: {}, skipped: {}",
+            self.read_lines, self.parsed, self.skipped
+        )
+    }
+}
+
+/// Seconds since the Unix epoch, negative before it.
+pub fn unix_secs(t: std::time::SystemTime) -> Option<i64> {
+    match t.duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => i64::try_from(d.as_secs()).ok(),
+        Err(e) => i64::try_from(e.duration().as_secs()).ok().map(|s| -s),
+    }
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_unix_secs(secs: i64) -> String {
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    // Civil-from-days conversion (proleptic Gregorian calendar).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_unix_timestamps_as_utc() {
+        assert_eq!(format_unix_secs(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_unix_secs(951_827_696), "2000-02-29 12:34:56 UTC");
+        assert_eq!(format_unix_secs(-1), "1969-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn reader_skips_bad_lines_and_round_trips_the_writer() {
+        let entry = IndexEntry {
+            mtime: Some(1),
+            ..IndexEntry::new("/a.iso", 7)
+        };
+        let mut writer = IndexWriter::new(Vec::new());
+        writer.write(&entry).unwrap();
+        let mut text = writer.finish().unwrap();
+        text.extend_from_slice(b"\nnot json\n{\"path\":\"/b\",\"size\":1}\n");
+
+        let mut reader = IndexReader::new(text.as_slice());
+        let paths: Vec<String> = reader.by_ref().map(|e| e.path).collect();
+        assert_eq!(paths, ["/a.iso", "/b"]);
+        assert_eq!((reader.parsed(), reader.skipped()), (2, 1));
+    }
+}
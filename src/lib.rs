@@ -0,0 +1,132 @@
+//! Find the largest files under a directory, or in an index of one.
+//!
+//! The `largest-file-finder` binary is a thin command-line wrapper around this crate. The pieces
+//! are usable on their own:
+//!
+//! - [`Scanner`] walks a directory tree, feeding the matching files to [`Collector`]s such as a
+//!   [`TopN`], a [`GroupBy`] breakdown or an [`IndexWriter`], all in one pass. It reads the tree
+//!   through a [`vfs::Filesystem`], which can be the in-memory [`vfs::MemFs`] instead of the disk.
+//! - [`Matcher`] decides which files count, from `--include`/`--exclude` style regexes and a query
+//!   [`Expr`] such as `ext in (mkv, mp4) AND size>1GB`.
+//! - [`IndexReader`] and [`IndexWriter`] read and write the JSONL index, one [`IndexEntry`] per
+//!   line, so a tree can be scanned once and queried many times.
+//! - [`TopN`] keeps the best-ranked files according to a [`Ranking`].
+//! - [`Pipeline`] filters the files of any [`EntrySource`] into collectors; the directory walk and
+//!   [`IndexReader`] are sources, and [`Entries`] turns any list of [`IndexEntry`] into one.
+//!
+//! ```no_run
+//! use largest_file_finder::{Expr, Matcher, Ranking, Scanner, SortKey, TopN};
+//!
+//! # fn main() -> largest_file_finder::Result<()> {
+//! let matcher = Matcher::new().with_query(Expr::parse("ext:iso OR size>4GB")?);
+//! let mut top = TopN::new(10, Ranking::new(SortKey::Size, false));
+//! Scanner::new("/srv").matcher(matcher).one_file_system(true).scan(&mut [&mut top])?;
+//! for file in top.into_sorted_vec() {
+//!     println!("{}\t{}", file.size, file.path.display());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+
+pub mod archive;
+pub mod collect;
+mod ignore_files;
+pub mod index;
+pub mod mounts;
+pub mod query;
+pub mod rank;
+pub mod scan;
+pub mod scan_errors;
+pub mod source;
+pub mod vfs;
+
+pub use collect::{Collector, DuplicateBuckets, GroupBy, Histogram, Totals};
+pub use index::{IndexEntry, IndexReader, IndexWriter};
+pub use query::{parse_size_bytes, CmpOp, Expr, Matcher, QueryMacros};
+pub use rank::{AgeField, Ranking, SizedPath, SortKey, TopN};
+pub use scan::{ScanOutcome, Scanner};
+pub use source::{Entries, EntrySource, Pipeline, Record};
+
+/// Errors returned by this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A query expression that does not parse; the message gives the byte position
+    Query(String),
+    /// An error inside the definition of a query macro, used at byte `at` of the expression
+    Macro {
+        name: String,
+        definition: String,
+        at: usize,
+        source: Box<Error>,
+    },
+    /// A regex, from a query or an include/exclude pattern, that does not compile
+    Regex {
+        pattern: String,
+        source: regex::Error,
+    },
+    /// A size such as `10MB` that does not parse
+    Size(String),
+    /// The directory to scan does not exist
+    RootNotFound(PathBuf),
+    /// Reading or writing a file (an index, the errors log) failed
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    /// An archive listed with `inspect_archives` that could not be read
+    Archive {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn io(context: impl Into<String>, source: impl Into<std::io::Error>) -> Self {
+        Error::Io {
+            context: context.into(),
+            source: source.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Query(message) | Error::Size(message) => f.write_str(message),
+            Error::Macro {
+                name,
+                definition,
+                at,
+                ..
+            } => write!(
+                f,
+                "In query macro @{name} = {definition:?} (used at byte {at})"
+            ),
+            Error::Regex { pattern, .. } => write!(f, "Invalid regex: {pattern}"),
+            Error::RootNotFound(root) => {
+                write!(f, "Root path does not exist: {}", root.display())
+            }
+            Error::Io { context, .. } => f.write_str(context),
+            Error::Archive { path, .. } => {
+                write!(f, "Failed to read archive {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Macro { source, .. } => Some(source.as_ref()),
+            Error::Regex { source, .. } => Some(source),
+            Error::Io { source, .. } | Error::Archive { source, .. } => Some(source),
+            Error::Query(_) | Error::Size(_) | Error::RootNotFound(_) => None,
+        }
+    }
+}
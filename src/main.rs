@@ -1,859 +1,1135 @@
 use anyhow::Context;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use cli::{Cli, Command, IndexCommand};
 use humansize::{format_size, BINARY};
-use regex::{Regex, RegexBuilder};
-use serde::{Deserialize, Serialize};
-use std::cmp::{Ordering, Reverse};
-use std::collections::BinaryHeap;
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use largest_file_finder::index::{format_unix_secs, unix_secs};
+use largest_file_finder::{
+    mounts, parse_size_bytes, Collector, DuplicateBuckets, IndexEntry, IndexReader, IndexWriter,
+    Matcher, Pipeline, QueryMacros, Ranking, Scanner, SizedPath, TopN,
+};
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
-
-#[derive(Parser, Debug)]
-#[command(
-    name = "largest-file-finder",
-    about = "Find the largest files under a directory"
-)]
-struct Args {
-    /// Root directory to scan (default: /)
-    #[arg(default_value = "/")]
-    root: PathBuf,
-
-    /// Number of largest files to print
-    #[arg(long, default_value_t = 20)]
-    top: usize,
-
-    /// Ignore files smaller than this many bytes
-    #[arg(long, default_value_t = 0)]
-    min_bytes: u64,
-
-    /// Write a pre-built index (JSONL) while scanning
-    #[arg(long, value_name = "FILE", conflicts_with = "index_read")]
-    index_write: Option<PathBuf>,
-
-    /// Read from an existing index (JSONL) instead of scanning
-    #[arg(long, value_name = "FILE", conflicts_with = "index_write")]
-    index_read: Option<PathBuf>,
-
-    /// Boolean query expression (AND/OR/NOT, parentheses) over name/path regex + ext/size
-    ///
-    /// Examples:
-    ///   name:/\\.(mp4|mkv)$/ AND size>1GB
-    ///   path:/Downloads/ AND NOT name:/\\.part$/
-    #[arg(long, value_name = "EXPR")]
-    query: Option<String>,
-
-    /// Include filter (case-insensitive regex) applied to full path; repeatable
-    #[arg(long, value_name = "REGEX")]
-    include: Vec<String>,
-
-    /// Exclude filter (case-insensitive regex) applied to full path; repeatable
-    #[arg(long, value_name = "REGEX")]
-    exclude: Vec<String>,
-
-    /// Follow symlinks while walking
-    #[arg(long, default_value_t = false)]
-    follow_symlinks: bool,
-
-    /// Do not cross filesystem boundaries (best-effort)
-    #[arg(long, default_value_t = false)]
-    one_file_system: bool,
-
-    /// Print progress occasionally (paths skipped / visited)
-    #[arg(long, default_value_t = false)]
-    verbose: bool,
-}
-
-#[derive(Debug, Clone, Eq, PartialEq)]
-struct SizedPath {
-    size: u64,
-    path: PathBuf,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct IndexEntry {
-    path: String,
-    size: u64,
-}
-
-#[derive(Debug, Clone)]
-struct Matcher {
-    query: Option<Expr>,
-    include: Vec<Regex>,
-    exclude: Vec<Regex>,
-}
 
-impl Matcher {
-    fn from_args(args: &Args) -> anyhow::Result<Self> {
-        let mut include = Vec::with_capacity(args.include.len());
-        for pat in &args.include {
-            let re = RegexBuilder::new(pat)
-                .case_insensitive(true)
-                .build()
-                .with_context(|| format!("Invalid --include regex: {pat}"))?;
-            include.push(re);
-        }
-
-        let mut exclude = Vec::with_capacity(args.exclude.len());
-        for pat in &args.exclude {
-            let re = RegexBuilder::new(pat)
-                .case_insensitive(true)
-                .build()
-                .with_context(|| format!("Invalid --exclude regex: {pat}"))?;
-            exclude.push(re);
-        }
-
-        let query = match args.query.as_deref() {
-            Some(expr_str) => Some(ParserExpr::new(expr_str).parse().with_context(|| {
-                format!("Invalid --query expression: {expr_str}")
-            })?),
-            None => None,
-        };
+mod actions;
+mod checks;
+mod checksum;
+mod cli;
+mod compress;
+mod config;
+mod dedupe;
+mod diff;
+mod git_index;
+mod image;
+mod relocate;
+mod report;
+mod stats;
+mod trash;
+mod tree;
+mod tui;
 
-        Ok(Self {
-            query,
-            include,
-            exclude,
-        })
-    }
-
-    fn matches_path_str(&self, path: &str, size: u64) -> bool {
-        for re in &self.exclude {
-            if re.is_match(path) {
-                return false;
+fn main() -> anyhow::Result<()> {
+    let (cli, macros) = parse_args()?;
+    let verbose = cli.global.verbose;
+
+    match &cli.command {
+        None => {
+            let legacy = &cli.legacy;
+            if legacy.index_write.is_some() {
+                eprintln!("Warning: --index-write is deprecated; use `index build ROOT -o FILE`");
             }
+            if legacy.index_read.is_some() {
+                eprintln!("Warning: --index-read is deprecated; use `query FILE`");
+            }
+            let source = match &legacy.index_read {
+                Some(index) => Source::Index(index),
+                None => Source::Scan {
+                    root: &legacy.scan.root,
+                    walk: &legacy.scan.walk,
+                },
+            };
+            let scan = &legacy.scan;
+            run_ranked(
+                source,
+                legacy.index_write.as_deref(),
+                &scan.filter,
+                &scan.rank,
+                &scan.output,
+                &scan.checks,
+                &scan.actions,
+                verbose,
+                &macros,
+            )
         }
-
-        // If any --include filters are provided, the path must match at least one.
-        if !self.include.is_empty() && !self.include.iter().any(|re| re.is_match(path)) {
-            return false;
+        Some(Command::Scan(scan)) => run_ranked(
+            Source::Scan {
+                root: &scan.root,
+                walk: &scan.walk,
+            },
+            None,
+            &scan.filter,
+            &scan.rank,
+            &scan.output,
+            &scan.checks,
+            &scan.actions,
+            verbose,
+            &macros,
+        ),
+        Some(Command::Query(query)) => run_ranked(
+            Source::Index(&query.index),
+            None,
+            &query.filter,
+            &query.rank,
+            &query.output,
+            &query.checks,
+            &query.actions,
+            verbose,
+            &macros,
+        ),
+        Some(Command::Index(IndexCommand::Build { root, output, walk })) => {
+            run_index_build(root, output, walk, verbose)
         }
-
-        if let Some(expr) = &self.query {
-            return expr.eval(path, size);
+        Some(Command::Index(IndexCommand::Update { index, root, walk })) => {
+            run_index_update(index, root, walk, verbose)
+        }
+        Some(Command::Diff(args)) => run_diff(args, &args.filter.matcher(&macros)?),
+        Some(Command::Stats(args)) => run_stats(args, &args.filter.matcher(&macros)?, verbose),
+        Some(Command::Dupes(args)) => run_dupes(args, &args.filter.matcher(&macros)?, verbose),
+        Some(Command::Watch(args)) => run_watch(args, &args.filter.matcher(&macros)?, verbose),
+        Some(Command::Tui(args)) => {
+            let matcher = args.filter.matcher(&macros)?;
+            run_tui(args, &matcher, verbose, macros.clone())
+        }
+        Some(Command::Restore {
+            paths,
+            confirm,
+            safety,
+        }) => run_restore(paths, confirm, safety),
+        Some(Command::Image { path, filter, rank }) => run_image(
+            path,
+            filter.min_bytes,
+            rank.top,
+            &filter.matcher(&macros)?,
+            rank.ranking(),
+        ),
+        Some(Command::GitCheck {
+            path,
+            max_size,
+            allow,
+            allowlist,
+            filter,
+            rank,
+            checks,
+        }) => {
+            let repo = git_index::Repo::discover(path.as_deref().unwrap_or(Path::new(".")))?;
+            let allowed = git_allowlist(&repo, allow, allowlist.as_deref())?;
+            run_git_check(
+                &repo,
+                max_size,
+                &allowed,
+                filter,
+                rank,
+                checks,
+                &filter.matcher(&macros)?,
+                &macros,
+            )
         }
-
-        true
-    }
-}
-
-impl Ord for SizedPath {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Natural (max-heap) ordering: larger sizes first.
-        // Tie-breaker ensures deterministic ordering.
-        self.size
-            .cmp(&other.size)
-            // Reverse path ordering so that, for equal sizes, lexicographically *smaller* paths win
-            // when we maintain a min-heap via Reverse<SizedPath>.
-            .then_with(|| other.path.cmp(&self.path))
     }
 }
 
-impl PartialOrd for SizedPath {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+/// Where the files to rank come from.
+#[derive(Debug, Clone, Copy)]
+enum Source<'a> {
+    Scan {
+        root: &'a Path,
+        walk: &'a cli::WalkArgs,
+    },
+    Index(&'a Path),
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-
-    let matcher = Matcher::from_args(&args)?;
-
-    let root = args.root;
-
-    let top_n = args.top.max(1);
-    let min_bytes = args.min_bytes;
-
-    if args.index_read.is_none() && !root.exists() {
-        anyhow::bail!("Root path does not exist: {}", root.display());
-    }
-
-    // If the user points directly at a file, treat it as a 1-item scan.
-    if args.index_read.is_none() && root.is_file() {
-        let md = std::fs::metadata(&root)?;
-        let size = md.len();
-        let path_str = root.to_string_lossy();
-        if size >= min_bytes && matcher.matches_path_str(&path_str, size) {
-            println!("#1\t{}\t{}", format_size(size, BINARY), root.display());
-        } else {
-            println!(
-                "No matching files >= {} bytes found at {}",
-                min_bytes,
-                root.display()
-            );
+impl<'a> Source<'a> {
+    fn from_args(args: &'a cli::SourceArgs) -> Self {
+        match &args.index {
+            Some(index) => Source::Index(index),
+            None => Source::Scan {
+                root: &args.root,
+                walk: &args.walk,
+            },
         }
-        return Ok(());
     }
 
-    let mut top_files: BinaryHeap<Reverse<SizedPath>> = BinaryHeap::with_capacity(top_n);
-
-    if let Some(index_path) = &args.index_read {
-        read_index_and_collect(
-            index_path,
-            &matcher,
-            top_n,
-            min_bytes,
-            &mut top_files,
-            args.verbose,
-        )?;
-    } else {
-        let mut index_writer = match &args.index_write {
-            Some(path) => Some(BufWriter::new(
-                File::create(path).with_context(|| format!("Failed to create index file: {}", path.display()))?,
-            )),
-            None => None,
-        };
-
-        scan_filesystem_and_collect(
-            &root,
-            args.follow_symlinks,
-            args.one_file_system,
-            args.verbose,
-            &matcher,
-            top_n,
-            min_bytes,
-            &mut top_files,
-            index_writer.as_mut(),
-        )?;
-
-        if let Some(mut w) = index_writer {
-            w.flush().context("Failed to flush index file")?;
+    /// The scanned directory; unknown for an index.
+    fn root(&self) -> Option<&'a Path> {
+        match self {
+            Source::Scan { root, .. } => Some(root),
+            Source::Index(_) => None,
         }
     }
 
-    let mut results: Vec<SizedPath> = top_files
-        .into_iter()
-        .map(|Reverse(sp)| sp)
-        .collect();
-
-    results.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
-
-    if results.is_empty() {
-        if let Some(index_path) = &args.index_read {
-            println!("No matching files found in index {}", index_path.display());
-        } else {
-            println!("No matching files found under {}", root.display());
-        }
-        return Ok(());
+    /// The directory actions on these results are confined to: `--within`, else the scan root.
+    fn action_root(&self, safety: &'a cli::SafetyArgs) -> anyhow::Result<&'a Path> {
+        safety.within.as_deref().or(self.root()).context(
+            "Acting on the results of an index needs --within DIR to confine the actions to",
+        )
     }
 
-    for (idx, item) in results.iter().enumerate() {
-        println!(
-            "#{}\t{}\t{}",
-            idx + 1,
-            format_size(item.size, BINARY),
-            item.path.display()
-        );
+    fn describe(&self) -> String {
+        match self {
+            Source::Scan { root, .. } => format!("scan {}", root.display()),
+            Source::Index(index) => format!("index {}", index.display()),
+        }
     }
-
-    Ok(())
 }
 
-fn scan_filesystem_and_collect(
-    root: &Path,
-    follow_symlinks: bool,
-    one_file_system: bool,
-    verbose: bool,
+/// Scans or reads `source` in one pass, handing the matches to `collectors`. Returns the number
+/// of errors met while scanning.
+fn collect(
+    source: Source,
     matcher: &Matcher,
-    top_n: usize,
     min_bytes: u64,
-    top_files: &mut BinaryHeap<Reverse<SizedPath>>,
-    mut index_writer: Option<&mut BufWriter<File>>,
-) -> anyhow::Result<()> {
-    let root_dev = if one_file_system {
-        device_id(root).ok()
-    } else {
-        None
-    };
-
-    let mut visited: u64 = 0;
-    let mut skipped: u64 = 0;
-
-    let walker = WalkDir::new(root)
-        .follow_links(follow_symlinks)
-        .same_file_system(one_file_system)
-        .into_iter();
-
-    for entry_result in walker {
-        let entry = match entry_result {
-            Ok(e) => e,
-            Err(_err) => {
-                skipped += 1;
-                continue;
+    verbose: bool,
+    collectors: &mut [&mut dyn Collector],
+) -> anyhow::Result<u64> {
+    match source {
+        Source::Index(index_path) => {
+            let mut reader = IndexReader::open(index_path)?;
+            Pipeline::new(matcher, min_bytes)
+                .verbose(verbose)
+                .run(&mut reader, collectors)?;
+            Ok(0)
+        }
+        Source::Scan { root, walk } => {
+            let mut scanner = Scanner::new(root)
+                .follow_symlinks(walk.follow_symlinks)
+                .one_file_system(walk.one_file_system)
+                .inspect_archives(walk.inspect_archives)
+                .ignore_files(walk.ignore_files)
+                .mount_filter(mount_filter(walk))
+                .verbose(verbose)
+                .matcher(matcher.clone())
+                .min_bytes(min_bytes);
+            if let Some(log) = &walk.errors_log {
+                scanner = scanner.errors_log(log);
             }
-        };
+            let outcome = scanner.scan(collectors)?;
 
-        let md = match entry.metadata() {
-            Ok(m) => m,
-            Err(_) => {
-                skipped += 1;
-                continue;
+            if outcome.pruned > 0 {
+                eprintln!("Pruned {} directories without walking them", outcome.pruned);
             }
-        };
-
-        if !md.is_file() {
-            continue;
-        }
-
-        if let (Some(root_dev), true) = (root_dev.as_ref(), one_file_system) {
-            if let Ok(dev) = file_device_id(entry.path()) {
-                if &dev != root_dev {
-                    continue;
+            let errors = &outcome.errors;
+            eprintln!("{}", errors.summary(outcome.files, outcome.bytes));
+            if errors.total() > 0 {
+                match errors.log_path() {
+                    Some(path) => eprintln!("Error details written to {}", path.display()),
+                    None => eprintln!("Pass --errors-log FILE for the full list"),
                 }
             }
+            Ok(errors.total())
         }
+    }
+}
 
-        visited += 1;
-        let size = md.len();
-        let path_cow = entry.path().to_string_lossy();
-
-        if let Some(w) = index_writer.as_mut() {
-            let rec = IndexEntry {
-                path: path_cow.to_string(),
-                size,
-            };
-            serde_json::to_writer(w.by_ref(), &rec)
-                .context("Failed to write JSON record to index")?;
-            w.write_all(b"\n")
-                .context("Failed to write newline to index")?;
-        }
+/// `scan` and `query`: rank the matches, report, check thresholds and act on the results.
+#[allow(clippy::too_many_arguments)]
+fn run_ranked(
+    source: Source,
+    index_write: Option<&Path>,
+    filter: &cli::FilterArgs,
+    rank: &cli::RankArgs,
+    output: &cli::OutputArgs,
+    check_args: &cli::CheckArgs,
+    action_args: &cli::ActionArgs,
+    verbose: bool,
+    macros: &QueryMacros,
+) -> anyhow::Result<()> {
+    let matcher = filter.matcher(macros)?;
+    let ranking = rank.ranking();
+    let checks = check_args
+        .fail_if
+        .iter()
+        .map(|c| {
+            checks::Check::parse(c, macros).with_context(|| format!("Invalid --fail-if check: {c}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-        if size < min_bytes {
-            continue;
-        }
+    let report_out = match output.report.as_slice() {
+        [] => None,
+        [format, file] if format.eq_ignore_ascii_case("html") => Some(PathBuf::from(file)),
+        [format, ..] => anyhow::bail!("Unsupported --report format: {format} (expected html)"),
+    };
 
-        if !matcher.matches_path_str(path_cow.as_ref(), size) {
-            continue;
-        }
+    let action_root = action_args
+        .any()
+        .then(|| source.action_root(&action_args.safety))
+        .transpose()?;
+    let top_n = rank.top.max(1);
+    let min_bytes = filter.min_bytes;
+
+    let mut top = TopN::new(top_n, ranking);
+    // The report needs every match, not just the top N; the checks only what they flag.
+    let mut matched: Option<Vec<IndexEntry>> = report_out.is_some().then(Vec::new);
+    let mut check_collector = checks::CheckCollector::new(&checks);
+
+    let mut index_writer = index_write.map(IndexWriter::create).transpose()?;
+    let mut collectors: Vec<&mut dyn Collector> = vec![&mut top];
+    if let Some(w) = index_writer.as_mut() {
+        collectors.push(w);
+    }
+    if let Some(m) = matched.as_mut() {
+        collectors.push(m);
+    }
+    if !checks.is_empty() {
+        collectors.push(&mut check_collector);
+    }
+    let error_count = collect(source, &matcher, min_bytes, verbose, &mut collectors)?;
+    if let Some(w) = index_writer {
+        w.finish()?;
+    }
 
-        let candidate = SizedPath {
-            size,
-            path: entry.path().to_path_buf(),
-        };
+    let results = top.into_sorted_vec();
 
-        consider_candidate(top_files, top_n, candidate);
-
-        if verbose && visited % 200_000 == 0 {
-            let current_floor = top_files
-                .peek()
-                .map(|Reverse(sp)| sp.size)
-                .unwrap_or(0);
-            eprintln!(
-                "Visited: {visited}, skipped: {skipped}, collected: {}, current top-floor: {} ({current_floor} bytes)",
-                top_files.len(),
-                format_size(current_floor, BINARY)
-            );
-        }
+    if let (Some(out), Some(records)) = (&report_out, &matched) {
+        write_html_report(out, source.describe(), &results, records)?;
+        eprintln!("Wrote HTML report to {}", out.display());
     }
 
-    Ok(())
-}
+    let violations = check_collector.evaluate(error_count);
+    checks::report(&checks, &violations, check_args.violations.as_deref())?;
+    let exit_code = violations.first().map(|v| v.exit_code);
 
-fn read_index_and_collect(
-    index_path: &Path,
-    matcher: &Matcher,
-    top_n: usize,
-    min_bytes: u64,
-    top_files: &mut BinaryHeap<Reverse<SizedPath>>,
-    verbose: bool,
-) -> anyhow::Result<()> {
-    let file = File::open(index_path)
-        .with_context(|| format!("Failed to open index file: {}", index_path.display()))?;
-    let reader = BufReader::new(file);
-
-    let mut read_lines: u64 = 0;
-    let mut parsed: u64 = 0;
-    let mut skipped: u64 = 0;
-
-    for line_res in reader.lines() {
-        read_lines += 1;
-        let line = match line_res {
-            Ok(l) => l,
-            Err(_) => {
-                skipped += 1;
-                continue;
+    if results.is_empty() {
+        match source {
+            Source::Index(index_path) => {
+                println!("No matching files found in index {}", index_path.display())
             }
-        };
-
-        if line.trim().is_empty() {
-            continue;
-        }
-
-        let rec: IndexEntry = match serde_json::from_str(&line) {
-            Ok(r) => r,
-            Err(_) => {
-                skipped += 1;
-                continue;
+            Source::Scan { root, .. } => {
+                println!("No matching files found under {}", root.display())
             }
-        };
-        parsed += 1;
-
-        if rec.size < min_bytes {
-            continue;
         }
-
-        if !matcher.matches_path_str(&rec.path, rec.size) {
-            continue;
+        if let Some(code) = exit_code {
+            std::process::exit(code);
         }
+        return Ok(());
+    }
 
-        let candidate = SizedPath {
-            size: rec.size,
-            path: PathBuf::from(rec.path),
-        };
-        consider_candidate(top_files, top_n, candidate);
-
-        if verbose && parsed % 500_000 == 0 {
-            let current_floor = top_files
-                .peek()
-                .map(|Reverse(sp)| sp.size)
-                .unwrap_or(0);
-            eprintln!(
-                "Index lines: {read_lines}, parsed: {parsed}, skipped: {skipped}, collected: {}, current top-floor: {} ({current_floor} bytes)",
-                top_files.len(),
-                format_size(current_floor, BINARY)
-            );
-        }
+    if output.by_filesystem {
+        print_by_filesystem(&results);
+    } else {
+        print_results(&results, |_| None);
     }
 
+    if let Some(root) = action_root {
+        run_actions(action_args, root, &results)?;
+    }
+
+    if let Some(code) = exit_code {
+        std::process::exit(code);
+    }
     Ok(())
 }
 
-fn consider_candidate(
-    top_files: &mut BinaryHeap<Reverse<SizedPath>>,
-    top_n: usize,
-    candidate: SizedPath,
-) {
-    if top_files.len() < top_n {
-        top_files.push(Reverse(candidate));
-    } else if let Some(Reverse(current_smallest)) = top_files.peek() {
-        if candidate.cmp(current_smallest) == Ordering::Greater {
-            top_files.pop();
-            top_files.push(Reverse(candidate));
+/// Applies the action selected in `args` to the ranked results.
+fn run_actions(args: &cli::ActionArgs, root: &Path, results: &[SizedPath]) -> anyhow::Result<()> {
+    let confirm = actions::Confirm::from_flags(args.confirm.yes, args.confirm.interactive);
+    let mut ctx = action_context(
+        &args.safety.deny,
+        args.safety.audit_log.as_deref(),
+        root,
+        confirm,
+    )?;
+    let items = results.iter().map(|r| (r.path.as_path(), r.size));
+    println!();
+    if let Some(dest) = &args.relocate_to {
+        let relocator = relocate::Relocator::new(ctx.guard.root(), dest, args.leave_symlink)?;
+        actions::run_on_results(&mut ctx, "relocate", "relocated", items, confirm, |path| {
+            relocator.relocate(path)
+        })?;
+    } else if let Some(codec) = args.compress {
+        let compressor = compress::Compressor::new(codec, args.compress_ratio);
+        actions::run_on_results(&mut ctx, "compress", "compressed", items, confirm, |path| {
+            compressor.compress(path)
+        })?;
+        if confirm != actions::Confirm::DryRun {
+            println!("Reclaimed {}", format_size(compressor.reclaimed(), BINARY));
+        }
+    } else if let Some(mode) = args.dedupe {
+        let sets = dedupe::find_duplicates(items);
+        let deduper = dedupe::Deduper::new(mode, sets, &ctx.guard);
+        println!(
+            "Duplicate sets: {} (keeping the highest-ranked copy of each)",
+            deduper.sets()
+        );
+        actions::run_on_results(
+            &mut ctx,
+            "dedupe",
+            "linked",
+            deduper.copies(),
+            confirm,
+            |path| deduper.dedupe(path),
+        )?;
+        if confirm == actions::Confirm::DryRun {
+            println!(
+                "Projected savings: {}",
+                format_size(deduper.projected_savings(), BINARY)
+            );
         }
+    } else if args.trash {
+        actions::run_on_results(
+            &mut ctx,
+            "trash",
+            "trashed",
+            items,
+            confirm,
+            trash::trash_file,
+        )?;
+    } else {
+        actions::run_on_results(
+            &mut ctx,
+            "delete",
+            "deleted",
+            items,
+            confirm,
+            actions::delete_file,
+        )?;
     }
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-enum Expr {
-    And(Box<Expr>, Box<Expr>),
-    Or(Box<Expr>, Box<Expr>),
-    Not(Box<Expr>),
-    Pred(Predicate),
+/// Every file in `source` that passes `matcher` and `min_bytes`.
+fn matched_entries(
+    source: Source,
+    matcher: &Matcher,
+    min_bytes: u64,
+    verbose: bool,
+) -> anyhow::Result<Vec<IndexEntry>> {
+    let mut matched: Vec<IndexEntry> = Vec::new();
+    collect(source, matcher, min_bytes, verbose, &mut [&mut matched])?;
+    Ok(matched)
 }
 
-impl Expr {
-    fn eval(&self, path: &str, size: u64) -> bool {
-        match self {
-            Expr::And(a, b) => a.eval(path, size) && b.eval(path, size),
-            Expr::Or(a, b) => a.eval(path, size) || b.eval(path, size),
-            Expr::Not(inner) => !inner.eval(path, size),
-            Expr::Pred(p) => p.eval(path, size),
-        }
+/// Scans `root` into a new index at `output`, returning every file seen. The index is written
+/// next to `output` first and renamed over it once the scan has finished.
+fn write_index(
+    root: &Path,
+    output: &Path,
+    walk: &cli::WalkArgs,
+    verbose: bool,
+) -> anyhow::Result<Vec<IndexEntry>> {
+    let mut partial = output.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let mut writer = IndexWriter::create(&partial)?;
+    let source = Source::Scan { root, walk };
+    let mut entries: Vec<IndexEntry> = Vec::new();
+    let written = collect(
+        source,
+        &Matcher::new(),
+        0,
+        verbose,
+        &mut [&mut entries, &mut writer],
+    )
+    .and_then(|_| Ok(writer.finish()?))
+    .and_then(|_| {
+        std::fs::rename(&partial, output)
+            .with_context(|| format!("Failed to replace index file: {}", output.display()))
+    });
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(err);
     }
+    Ok(entries)
 }
 
-#[derive(Debug, Clone)]
-enum CmpOp {
-    Lt,
-    Lte,
-    Gt,
-    Gte,
-    Eq,
+fn run_index_build(
+    root: &Path,
+    output: &Path,
+    walk: &cli::WalkArgs,
+    verbose: bool,
+) -> anyhow::Result<()> {
+    let entries = write_index(root, output, walk, verbose)?;
+    let bytes: u64 = entries.iter().map(|e| e.size).sum();
+    println!(
+        "Wrote index {}: {} files, {}",
+        output.display(),
+        entries.len(),
+        format_size(bytes, BINARY)
+    );
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-enum Predicate {
-    PathRegex(Regex),
-    NameRegex(Regex),
-    ExtEq(String),
-    SizeCmp { op: CmpOp, bytes: u64 },
+fn run_index_update(
+    index: &Path,
+    root: &Path,
+    walk: &cli::WalkArgs,
+    verbose: bool,
+) -> anyhow::Result<()> {
+    let old = matched_entries(Source::Index(index), &Matcher::new(), 0, verbose)?;
+    let new = write_index(root, index, walk, verbose)?;
+    let changes = diff::changes(
+        old.into_iter().map(|e| (e.path, e.size)),
+        new.into_iter().map(|e| (e.path, e.size)),
+    );
+    let added = changes.iter().filter(|c| c.old.is_none()).count();
+    let removed = changes.iter().filter(|c| c.new.is_none()).count();
+    let delta: i128 = changes.iter().map(diff::Change::delta).sum();
+    println!(
+        "Updated {}: +{added} added, -{removed} removed, ~{} changed ({})",
+        index.display(),
+        changes.len() - added - removed,
+        diff::format_delta(delta)
+    );
+    Ok(())
 }
 
-impl Predicate {
-    fn eval(&self, path: &str, size: u64) -> bool {
-        match self {
-            Predicate::PathRegex(re) => re.is_match(path),
-            Predicate::NameRegex(re) => Path::new(path)
-                .file_name()
-                .and_then(|s| s.to_str())
-                .is_some_and(|name| re.is_match(name)),
-            Predicate::ExtEq(ext) => Path::new(path)
-                .extension()
-                .and_then(|s| s.to_str())
-                .is_some_and(|e| e.eq_ignore_ascii_case(ext)),
-            Predicate::SizeCmp { op, bytes } => match op {
-                CmpOp::Lt => size < *bytes,
-                CmpOp::Lte => size <= *bytes,
-                CmpOp::Gt => size > *bytes,
-                CmpOp::Gte => size >= *bytes,
-                CmpOp::Eq => size == *bytes,
-            },
-        }
+fn run_diff(args: &cli::DiffArgs, matcher: &Matcher) -> anyhow::Result<()> {
+    let min_bytes = args.filter.min_bytes;
+    let read = |index: &Path| -> anyhow::Result<Vec<(String, u64)>> {
+        Ok(
+            matched_entries(Source::Index(index), matcher, min_bytes, false)?
+                .into_iter()
+                .map(|e| (e.path, e.size))
+                .collect(),
+        )
+    };
+    let changes = diff::changes(read(&args.old)?, read(&args.new)?);
+    if changes.is_empty() {
+        println!(
+            "No changes between {} and {}",
+            args.old.display(),
+            args.new.display()
+        );
+        return Ok(());
+    }
+    for (idx, change) in changes.iter().take(args.top.max(1)).enumerate() {
+        println!(
+            "#{}\t{}\t{}\t({})",
+            idx + 1,
+            change.format_delta(),
+            change.path,
+            change.describe()
+        );
     }
+
+    let grown: i128 = changes.iter().map(|c| c.delta().max(0)).sum();
+    let shrunk: i128 = changes.iter().map(|c| c.delta().min(0)).sum();
+    println!();
+    println!(
+        "{} changed files: +{} / -{}",
+        changes.len(),
+        format_size(grown as u64, BINARY),
+        format_size(shrunk.unsigned_abs() as u64, BINARY)
+    );
+    Ok(())
 }
 
-struct ParserExpr<'a> {
-    s: &'a str,
-    i: usize,
+fn run_stats(args: &cli::StatsArgs, matcher: &Matcher, verbose: bool) -> anyhow::Result<()> {
+    let source = Source::from_args(&args.source);
+    let mut stats = stats::Stats::default();
+    collect(
+        source,
+        matcher,
+        args.filter.min_bytes,
+        verbose,
+        &mut [&mut stats],
+    )?;
+    stats.print(args.top.max(1));
+    Ok(())
 }
 
-impl<'a> ParserExpr<'a> {
-    fn new(s: &'a str) -> Self {
-        Self { s, i: 0 }
-    }
+fn run_dupes(args: &cli::DupesArgs, matcher: &Matcher, verbose: bool) -> anyhow::Result<()> {
+    let source = Source::from_args(&args.source);
+    // Only files of equal size need hashing; entries inside archives cannot be hashed in place.
+    let mut buckets = DuplicateBuckets::new();
+    collect(
+        source,
+        matcher,
+        args.filter.min_bytes,
+        verbose,
+        &mut [&mut buckets],
+    )?;
+    let paths: Vec<(PathBuf, u64)> = buckets
+        .into_candidates()
+        .into_iter()
+        .flatten()
+        .map(|r| (r.path, r.entry.size))
+        .collect();
 
-    fn parse(mut self) -> anyhow::Result<Expr> {
-        let expr = self.parse_or()?;
-        self.skip_ws();
-        if self.i != self.s.len() {
-            anyhow::bail!("Unexpected trailing input at byte {}", self.i);
+    let wasted = |set: &dedupe::DuplicateSet| set.iter().skip(1).map(|(_, size)| size).sum::<u64>();
+    let mut sets = dedupe::find_duplicates(paths.iter().map(|(p, s)| (p.as_path(), *s)));
+    sets.sort_by(|a, b| wasted(b).cmp(&wasted(a)).then_with(|| a[0].0.cmp(&b[0].0)));
+    if sets.is_empty() {
+        println!("No duplicate files found in {}", source.describe());
+        return Ok(());
+    }
+    for (idx, set) in sets.iter().take(args.top.max(1)).enumerate() {
+        println!(
+            "#{}\t{} copies of {}\t{} wasted",
+            idx + 1,
+            set.len(),
+            format_size(set[0].1, BINARY),
+            format_size(wasted(set), BINARY)
+        );
+        for (path, _) in set {
+            println!("\t{}", path.display());
         }
-        Ok(expr)
     }
+    println!();
+    println!(
+        "Duplicate sets: {}, reclaimable {}",
+        sets.len(),
+        format_size(sets.iter().map(wasted).sum::<u64>(), BINARY)
+    );
+    Ok(())
+}
 
-    fn parse_or(&mut self) -> anyhow::Result<Expr> {
-        let mut left = self.parse_and()?;
-        loop {
-            self.skip_ws();
-            if self.consume_op("||") || self.consume_kw("OR") {
-                let right = self.parse_and()?;
-                left = Expr::Or(Box::new(left), Box::new(right));
-            } else {
-                break;
+/// Rescans `args.root` every `args.interval` seconds, printing the ranking once and then the files
+/// that entered or left it or changed size.
+fn run_watch(args: &cli::WatchArgs, matcher: &Matcher, verbose: bool) -> anyhow::Result<()> {
+    let ranking = args.rank.ranking();
+    let top_n = args.rank.top.max(1);
+    let source = Source::Scan {
+        root: &args.root,
+        walk: &args.walk,
+    };
+    let mut previous: Option<Vec<(String, u64)>> = None;
+    let mut round = 0;
+    loop {
+        let mut top = TopN::new(top_n, ranking);
+        collect(
+            source,
+            matcher,
+            args.filter.min_bytes,
+            verbose,
+            &mut [&mut top],
+        )?;
+        let results = top.into_sorted_vec();
+        let current: Vec<(String, u64)> = results
+            .iter()
+            .map(|r| (r.path.to_string_lossy().into_owned(), r.size))
+            .collect();
+
+        let now = unix_secs(std::time::SystemTime::now()).unwrap_or(0);
+        match previous {
+            None => {
+                println!(
+                    "{}: top {} under {}",
+                    format_unix_secs(now),
+                    top_n,
+                    args.root.display()
+                );
+                print_results(&results, |_| None);
+            }
+            Some(previous) => {
+                let changes = diff::changes(previous, current.clone());
+                if !changes.is_empty() {
+                    println!();
+                    println!("{}:", format_unix_secs(now));
+                }
+                for change in &changes {
+                    let what = match (change.old, change.new) {
+                        (None, _) => "entered top".to_string(),
+                        (_, None) => "left top".to_string(),
+                        _ => change.describe(),
+                    };
+                    println!("{}\t{}\t({what})", change.format_delta(), change.path);
+                }
             }
         }
-        Ok(left)
-    }
+        previous = Some(current);
 
-    fn parse_and(&mut self) -> anyhow::Result<Expr> {
-        let mut left = self.parse_unary()?;
-        loop {
-            self.skip_ws();
-            if self.consume_op("&&") || self.consume_kw("AND") {
-                let right = self.parse_unary()?;
-                left = Expr::And(Box::new(left), Box::new(right));
-            } else {
-                break;
-            }
+        round += 1;
+        if args.count.is_some_and(|count| round >= count) {
+            return Ok(());
         }
-        Ok(left)
+        std::thread::sleep(std::time::Duration::from_secs(args.interval));
     }
+}
 
-    fn parse_unary(&mut self) -> anyhow::Result<Expr> {
-        self.skip_ws();
-        if self.consume_op("!") || self.consume_kw("NOT") {
-            let inner = self.parse_unary()?;
-            return Ok(Expr::Not(Box::new(inner)));
-        }
-        self.parse_primary()
+/// Parses the command line on top of the settings from the config file, and returns the query
+/// macros the config defines.
+fn parse_args() -> anyhow::Result<(Cli, QueryMacros)> {
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let mut cmd = Cli::command();
+    cmd.build();
+    let matches = cmd.clone().get_matches_from(&argv);
+    if let Err(err) = cli::check_top_level(&mut cmd, &matches) {
+        err.exit();
+    }
+    if let Some(name) = matches.subcommand_name().filter(|n| Path::new(n).is_dir()) {
+        eprintln!(
+            "Note: running the `{name}` subcommand; to scan the directory, use `scan ./{name}`"
+        );
     }
+    let explicit = matches.get_one::<PathBuf>("config");
+    let profile = matches.get_one::<String>("profile");
 
-    fn parse_primary(&mut self) -> anyhow::Result<Expr> {
-        self.skip_ws();
-        if self.consume_op("(") {
-            let inner = self.parse_or()?;
-            self.skip_ws();
-            if !self.consume_op(")") {
-                anyhow::bail!("Expected ')' at byte {}", self.i);
-            }
-            return Ok(inner);
+    let path = match explicit {
+        Some(path) => Some(path.clone()),
+        None => config::Config::default_path().filter(|p| p.exists()),
+    };
+    let Some(path) = path else {
+        if let Some(name) = profile {
+            anyhow::bail!("--profile {name} needs a config file (see --config)");
         }
+        return Ok((Cli::from_arg_matches(&matches)?, QueryMacros::new()));
+    };
 
-        let pred = self.parse_predicate()?;
-        Ok(Expr::Pred(pred))
+    // Settings apply to the subcommand being run, so they are parsed as its options.
+    let (mut leaf_cmd, mut leaf) = (&cmd, &matches);
+    while let Some((name, sub)) = leaf.subcommand() {
+        leaf_cmd = leaf_cmd
+            .find_subcommand(name)
+            .context("Unknown subcommand")?;
+        leaf = sub;
     }
 
-    fn parse_predicate(&mut self) -> anyhow::Result<Predicate> {
-        self.skip_ws();
+    let config = config::Config::load(&path)?;
+    let from_config = config.args(profile.map(String::as_str), &cmd, leaf_cmd, |id| {
+        leaf.value_source(id) == Some(clap::parser::ValueSource::CommandLine)
+    })?;
+    // After the subcommand, but before any `--`.
+    let mut full = argv.clone();
+    let at = argv.iter().position(|a| a == "--").unwrap_or(argv.len());
+    full.splice(at..at, from_config);
+    let cli = Cli::try_parse_from(full)
+        .with_context(|| format!("Invalid options in config file {}", path.display()))?;
+    Ok((cli, config.queries().clone()))
+}
 
-        if self.peek_char() == Some('/') {
-            let re = self.parse_regex_literal()?;
-            return Ok(Predicate::PathRegex(re));
-        }
+fn mount_filter(args: &cli::WalkArgs) -> mounts::MountFilter {
+    let exclude = args.exclude_fs_types.as_ref().map(|types| {
+        types
+            .iter()
+            .filter(|t| !t.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+    });
+    mounts::MountFilter::new(
+        &mounts::read_mountinfo(),
+        &args.fs_types,
+        exclude.as_deref(),
+    )
+}
 
-        if self.consume_kw("name") {
-            self.expect_char(':')?;
-            let re = self.parse_regex_literal()?;
-            return Ok(Predicate::NameRegex(re));
-        }
+/// Prints the ranked results, one per line, with an optional note after the path.
+fn print_results(results: &[SizedPath], note: impl Fn(&SizedPath) -> Option<String>) {
+    for (idx, item) in results.iter().enumerate() {
+        print_result(idx + 1, item, note(item));
+    }
+}
 
-        if self.consume_kw("path") {
-            self.expect_char(':')?;
-            let re = self.parse_regex_literal()?;
-            return Ok(Predicate::PathRegex(re));
-        }
+fn print_result(rank: usize, item: &SizedPath, note: Option<String>) {
+    let size = match item.packed {
+        Some(packed) => format!(
+            "{} ({} packed)",
+            format_size(item.size, BINARY),
+            format_size(packed, BINARY)
+        ),
+        None => format_size(item.size, BINARY),
+    };
+    match note {
+        Some(note) => println!("#{rank}\t{size}\t{}\t({note})", item.path.display()),
+        None => println!("#{rank}\t{size}\t{}", item.path.display()),
+    }
+}
 
-        if self.consume_kw("ext") {
-            self.expect_char(':')?;
-            let val = self.parse_bare_value()?;
-            if val.is_empty() {
-                anyhow::bail!("ext: requires a value");
-            }
-            return Ok(Predicate::ExtEq(val));
-        }
+/// Prints the ranked results under a heading per filesystem, fullest share of results first.
+fn print_by_filesystem(results: &[SizedPath]) {
+    struct Group<'a> {
+        mount: Option<&'a mounts::Mount>,
+        bytes: u64,
+        items: Vec<(usize, &'a SizedPath)>,
+    }
 
-        if self.consume_kw("size") {
-            let op = self.parse_cmp_op()?;
-            let val = self.parse_bare_value()?;
-            let bytes = parse_size_bytes(&val).with_context(|| format!("Invalid size literal: {val}"))?;
-            return Ok(Predicate::SizeCmp { op, bytes });
+    let mounts = mounts::read_mountinfo();
+    let mut groups: Vec<Group> = Vec::new();
+    for (idx, item) in results.iter().enumerate() {
+        let abs = item
+            .path
+            .canonicalize()
+            .or_else(|_| std::path::absolute(&item.path))
+            .unwrap_or_else(|_| item.path.clone());
+        let mount = mounts::mount_for(&mounts, &abs);
+        let key = mount.map(|m| &m.mount_point);
+        match groups
+            .iter_mut()
+            .find(|g| g.mount.map(|m| &m.mount_point) == key)
+        {
+            Some(group) => {
+                group.bytes += item.size;
+                group.items.push((idx, item));
+            }
+            None => groups.push(Group {
+                mount,
+                bytes: item.size,
+                items: vec![(idx, item)],
+            }),
         }
-
-        anyhow::bail!("Expected predicate at byte {}", self.i)
     }
+    groups.sort_by_key(|g| std::cmp::Reverse(g.bytes));
 
-    fn parse_cmp_op(&mut self) -> anyhow::Result<CmpOp> {
-        self.skip_ws();
-        if self.consume_op(">=") {
-            return Ok(CmpOp::Gte);
-        }
-        if self.consume_op("<=") {
-            return Ok(CmpOp::Lte);
-        }
-        if self.consume_op(">") {
-            return Ok(CmpOp::Gt);
+    for (i, group) in groups.into_iter().enumerate() {
+        if i > 0 {
+            println!();
         }
-        if self.consume_op("<") {
-            return Ok(CmpOp::Lt);
+        let matched = format!(
+            "{} in {} results",
+            format_size(group.bytes, BINARY),
+            group.items.len()
+        );
+        match group.mount {
+            Some(m) => {
+                let space = match mounts::usage(&m.mount_point) {
+                    Some(u) => format!(
+                        "{} free of {} ({:.0}% used)",
+                        format_size(u.available, BINARY),
+                        format_size(u.total, BINARY),
+                        u.used_percent()
+                    ),
+                    None => "capacity unknown".to_string(),
+                };
+                println!(
+                    "{} ({} on {}): {space}; {matched}",
+                    m.mount_point.display(),
+                    m.fs_type,
+                    m.source
+                );
+            }
+            None => println!("(unknown filesystem): {matched}"),
         }
-        if self.consume_op("=") {
-            return Ok(CmpOp::Eq);
+        for (idx, item) in group.items {
+            print_result(idx + 1, item, None);
         }
-        anyhow::bail!("Expected comparison operator after size at byte {}", self.i)
     }
+}
 
-    fn parse_regex_literal(&mut self) -> anyhow::Result<Regex> {
-        self.skip_ws();
-        if self.peek_char() != Some('/') {
-            anyhow::bail!("Expected regex literal starting with '/' at byte {}", self.i);
+fn run_image(
+    path: &Path,
+    min_bytes: u64,
+    top: usize,
+    matcher: &Matcher,
+    ranking: Ranking,
+) -> anyhow::Result<()> {
+    let image = image::analyze(path)?;
+    let top_n = top.max(1);
+
+    let mut top = TopN::new(top_n, ranking);
+    let mut fates = std::collections::HashMap::new();
+    let (mut wasted_bytes, mut wasted_files) = (0u64, 0u64);
+    for file in &image.files {
+        if file.wasted.is_some() {
+            wasted_bytes += file.size;
+            wasted_files += 1;
         }
-        self.i += 1; // skip '/'
-
-        let mut pat = String::new();
-        // We treat '/' as the delimiter; a '/' can be included by escaping it as '\/'.
-        // All other backslashes must be preserved (e.g. /\./, /\d+/) so the regex semantics stay intact.
-        while self.i < self.s.len() {
-            let rest = &self.s[self.i..];
-            let Some(c) = rest.chars().next() else {
-                break;
-            };
-
-            if c == '/' {
-                self.i += 1;
-                let re = Regex::new(&pat).with_context(|| format!("Invalid regex: /{pat}/"))?;
-                return Ok(re);
-            }
-
-            if c == '\\' {
-                // Preserve backslashes, but allow escaping the delimiter.
-                self.i += 1;
-                if self.i >= self.s.len() {
-                    anyhow::bail!("Unterminated escape in regex literal");
-                }
-                let rest2 = &self.s[self.i..];
-                let next = rest2.chars().next().unwrap();
-                self.i += next.len_utf8();
-                if next == '/' {
-                    pat.push('/');
-                } else {
-                    pat.push('\\');
-                    pat.push(next);
-                }
-                continue;
-            }
-
-            self.i += c.len_utf8();
-            pat.push(c);
+        let vpath = image::Image::virtual_path(path, file);
+        if file.size < min_bytes || !matcher.matches(&vpath, file.size) {
+            continue;
         }
-
-        anyhow::bail!("Unterminated regex literal")
-    }
-
-    fn parse_bare_value(&mut self) -> anyhow::Result<String> {
-        self.skip_ws();
-        let start = self.i;
-        while let Some(c) = self.peek_char() {
-            if c.is_whitespace() || c == ')' || c == '(' {
-                break;
-            }
-            self.i += c.len_utf8();
+        let vpath = PathBuf::from(vpath);
+        if let Some(waste) = file.wasted {
+            fates.insert(vpath.clone(), waste);
         }
-        Ok(self.s[start..self.i].to_string())
+        top.offer_candidate(ranking.candidate(vpath, file.size, file.mtime, None));
     }
 
-    fn expect_char(&mut self, expected: char) -> anyhow::Result<()> {
-        self.skip_ws();
-        if self.peek_char() == Some(expected) {
-            self.i += expected.len_utf8();
-            Ok(())
-        } else {
-            anyhow::bail!("Expected '{expected}' at byte {}", self.i)
-        }
+    let results = top.into_sorted_vec();
+    if results.is_empty() {
+        println!("No matching files found in image {}", path.display());
     }
+    print_results(&results, |item| {
+        fates.get(&item.path).map(|waste| match waste {
+            image::Waste::Deleted(layer) => format!("wasted: deleted in layer-{}", layer + 1),
+            image::Waste::Replaced(layer) => format!("wasted: replaced in layer-{}", layer + 1),
+        })
+    });
 
-    fn skip_ws(&mut self) {
-        while let Some(c) = self.peek_char() {
-            if c.is_whitespace() {
-                self.i += c.len_utf8();
-            } else {
-                break;
-            }
-        }
+    let mut layers: Vec<(usize, &image::Layer)> = image.layers.iter().enumerate().collect();
+    layers.sort_by(|a, b| b.1.size.cmp(&a.1.size).then(a.0.cmp(&b.0)));
+    println!();
+    println!("Largest layers:");
+    for (idx, layer) in layers.into_iter().take(top_n) {
+        println!(
+            "layer-{}\t{}\t{} stored, {} wasted, {} files\t{}",
+            idx + 1,
+            format_size(layer.size, BINARY),
+            format_size(layer.blob_size, BINARY),
+            format_size(layer.wasted, BINARY),
+            layer.files,
+            layer.id
+        );
     }
+    println!(
+        "Wasted: {} in {wasted_files} files deleted or replaced by later layers",
+        format_size(wasted_bytes, BINARY)
+    );
+    Ok(())
+}
 
-    fn peek_char(&self) -> Option<char> {
-        self.s[self.i..].chars().next()
+/// Builds the `git-check` allowlist, rooted at the work tree of `repo`.
+fn git_allowlist(
+    repo: &git_index::Repo,
+    patterns: &[String],
+    file: Option<&Path>,
+) -> anyhow::Result<ignore::gitignore::Gitignore> {
+    let mut builder = ignore::gitignore::GitignoreBuilder::new(&repo.work_tree);
+    for pat in patterns {
+        builder
+            .add_line(None, pat)
+            .with_context(|| format!("Invalid --allow pattern: {pat}"))?;
     }
-
-    fn consume_op(&mut self, op: &str) -> bool {
-        if self.s[self.i..].starts_with(op) {
-            self.i += op.len();
-            true
-        } else {
-            false
+    if let Some(file) = file {
+        if let Some(err) = builder.add(file) {
+            return Err(err).with_context(|| format!("Invalid allowlist {}", file.display()));
         }
     }
+    builder.build().context("Invalid allowlist")
+}
 
-    fn consume_kw(&mut self, kw: &str) -> bool {
-        self.skip_ws();
-        let rest = &self.s[self.i..];
-        let mut chars = rest.chars();
-        let mut taken = String::new();
-        while let Some(c) = chars.next() {
-            if c.is_alphanumeric() || c == '_' {
-                taken.push(c);
-            } else {
-                break;
-            }
+#[allow(clippy::too_many_arguments)]
+fn run_git_check(
+    repo: &git_index::Repo,
+    max_size: &str,
+    allowed: &ignore::gitignore::Gitignore,
+    filter: &cli::FilterArgs,
+    rank: &cli::RankArgs,
+    check_args: &cli::CheckArgs,
+    matcher: &Matcher,
+    macros: &QueryMacros,
+) -> anyhow::Result<()> {
+    let limit =
+        parse_size_bytes(max_size).with_context(|| format!("Invalid --max-size: {max_size}"))?;
+    let mut checks = check_args
+        .fail_if
+        .iter()
+        .map(|c| {
+            checks::Check::parse(c, macros).with_context(|| format!("Invalid --fail-if check: {c}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    checks.insert(0, checks::Check::larger_than(max_size, limit));
+
+    let files = repo.files()?;
+    let top_n = rank.top.max(1);
+    let ranking = rank.ranking();
+
+    let mut top = TopN::new(top_n, ranking);
+    // Files the checks apply to: matched and not allowlisted.
+    let mut checked: Vec<IndexEntry> = Vec::new();
+    let (mut total, mut exempt) = (0u64, 0u64);
+    for file in &files {
+        // The index only keeps the low 32 bits of the size; the work tree copy has the rest, as
+        // long as it still is the staged version.
+        let size = match std::fs::metadata(repo.work_tree.join(&file.path)) {
+            Ok(md) if md.len() as u32 == file.size => md.len(),
+            _ => u64::from(file.size),
+        };
+        total += size;
+        if size < filter.min_bytes || !matcher.matches(&file.path, size) {
+            continue;
         }
-        if taken.eq_ignore_ascii_case(kw) {
-            self.i += taken.len();
-            true
+        if allowed
+            .matched_path_or_any_parents(&file.path, false)
+            .is_ignore()
+        {
+            exempt += u64::from(size > limit);
         } else {
-            false
+            checked.push(IndexEntry {
+                mtime: Some(file.mtime),
+                ..IndexEntry::new(file.path.clone(), size)
+            });
         }
+        let candidate = ranking.candidate(PathBuf::from(&file.path), size, Some(file.mtime), None);
+        top.offer_candidate(candidate);
     }
-}
 
-fn parse_size_bytes(s: &str) -> anyhow::Result<u64> {
-    let t = s.trim();
-    if t.is_empty() {
-        anyhow::bail!("empty size");
+    let results = top.into_sorted_vec();
+    if results.is_empty() {
+        println!("No matching files tracked in {}", repo.work_tree.display());
     }
-
-    let mut digits_end = 0;
-    for (idx, ch) in t.char_indices() {
-        if ch.is_ascii_digit() {
-            digits_end = idx + 1;
+    print_results(&results, |item| {
+        if item.size <= limit {
+            None
+        } else if checked.iter().any(|e| Path::new(&e.path) == item.path) {
+            Some("over limit".to_string())
         } else {
-            break;
+            Some("allowed".to_string())
         }
+    });
+
+    let violations = checks::evaluate(&checks, &checked, 0);
+    let over = violations.iter().filter(|v| v.is_file()).count();
+    println!(
+        "{} tracked files ({}); {over} over {} ({exempt} more allowed)",
+        files.len(),
+        format_size(total, BINARY),
+        format_size(limit, BINARY)
+    );
+    checks::report(&checks, &violations, check_args.violations.as_deref())?;
+    if let Some(v) = violations.first() {
+        std::process::exit(v.exit_code);
     }
+    Ok(())
+}
 
-    if digits_end == 0 {
-        anyhow::bail!("size must start with digits");
+/// Undoes `trash` actions from the audit log that have not been restored yet.
+fn run_restore(
+    only: &[PathBuf],
+    confirm: &cli::ConfirmArgs,
+    safety: &cli::SafetyArgs,
+) -> anyhow::Result<()> {
+    let log_path = safety
+        .audit_log
+        .clone()
+        .or_else(actions::AuditLog::default_path)
+        .context("No audit log location; pass --audit-log")?;
+
+    // Trashed location -> (original path, size), replaying the log in order.
+    let mut in_trash: std::collections::BTreeMap<PathBuf, (PathBuf, u64)> = Default::default();
+    for rec in actions::read_log(&log_path)? {
+        if rec.outcome != actions::Outcome::Done {
+            continue;
+        }
+        match (rec.action.as_str(), rec.target) {
+            ("trash", Some(target)) => {
+                in_trash.insert(PathBuf::from(target), (PathBuf::from(rec.path), rec.size));
+            }
+            ("restore", _) => {
+                in_trash.remove(Path::new(&rec.path));
+            }
+            _ => {}
+        }
     }
-
-    let num: u64 = t[..digits_end].parse()?;
-    let unit = t[digits_end..].trim().to_ascii_lowercase();
-    let mul: u64 = match unit.as_str() {
-        "" | "b" => 1,
-        "k" | "kb" | "kib" => 1024,
-        "m" | "mb" | "mib" => 1024_u64.pow(2),
-        "g" | "gb" | "gib" => 1024_u64.pow(3),
-        "t" | "tb" | "tib" => 1024_u64.pow(4),
-        _ => anyhow::bail!("unknown size unit: {unit}"),
+    let wanted = |original: &Path| {
+        only.is_empty()
+            || only
+                .iter()
+                .any(|p| p == original || std::path::absolute(p).is_ok_and(|abs| abs == original))
     };
-    num.checked_mul(mul)
-        .context("size overflow")
-}
+    in_trash.retain(|trashed, (original, _)| trashed.exists() && wanted(original));
 
-#[cfg(unix)]
-fn device_id(path: &Path) -> std::io::Result<u64> {
-    use std::os::unix::fs::MetadataExt;
-    let md = std::fs::metadata(path)?;
-    Ok(md.dev())
-}
+    if in_trash.is_empty() {
+        println!("No trashed files to restore in {}", log_path.display());
+        return Ok(());
+    }
 
-#[cfg(unix)]
-fn file_device_id(path: &Path) -> std::io::Result<u64> {
-    use std::os::unix::fs::MetadataExt;
-    let md = std::fs::metadata(path)?;
-    Ok(md.dev())
-}
+    // The guard hands `perform` the resolved trash path, so key the originals the same way.
+    let originals: std::collections::HashMap<PathBuf, PathBuf> = in_trash
+        .iter()
+        .filter_map(|(trashed, (original, _))| {
+            Some((trashed.canonicalize().ok()?, original.clone()))
+        })
+        .collect();
 
-#[cfg(not(unix))]
-fn device_id(_path: &Path) -> std::io::Result<u64> {
-    Err(std::io::Error::new(
-        std::io::ErrorKind::Unsupported,
-        "device id unsupported on this platform",
-    ))
+    let confirm = actions::Confirm::from_flags(confirm.yes, confirm.interactive);
+    let root = safety.within.as_deref().unwrap_or(Path::new("/"));
+    let mut ctx = action_context(&safety.deny, Some(&log_path), root, confirm)?;
+    actions::run_on_results(
+        &mut ctx,
+        "restore",
+        "restored",
+        in_trash.iter().map(|(t, (_, size))| (t.as_path(), *size)),
+        confirm,
+        |trashed| {
+            let original = originals
+                .get(trashed)
+                .context("Trashed file is not in the audit log")?;
+            // Older logs recorded paths as given; resolving them now would depend on this cwd.
+            if !original.is_absolute() {
+                anyhow::bail!(
+                    "the audit log records a relative original path {}; move it back by hand",
+                    original.display()
+                );
+            }
+            trash::restore_file(trashed, original)
+        },
+    )
 }
 
-#[cfg(not(unix))]
-fn file_device_id(_path: &Path) -> std::io::Result<u64> {
-    Err(std::io::Error::new(
-        std::io::ErrorKind::Unsupported,
-        "device id unsupported on this platform",
-    ))
+/// Builds the safety guard for `root` and opens the audit log unless this is a dry run.
+fn action_context(
+    deny: &[PathBuf],
+    audit_log: Option<&Path>,
+    root: &Path,
+    confirm: actions::Confirm,
+) -> anyhow::Result<actions::ActionContext> {
+    let guard = actions::Guard::new(root, deny)?;
+    let audit = if confirm == actions::Confirm::DryRun {
+        None
+    } else {
+        match audit_log
+            .map(Path::to_path_buf)
+            .or_else(actions::AuditLog::default_path)
+        {
+            Some(path) => Some(actions::AuditLog::open(&path)?),
+            None => {
+                eprintln!("Warning: no audit log location (set --audit-log or $HOME)");
+                None
+            }
+        }
+    };
+    Ok(actions::ActionContext { guard, audit })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn regex_literal_preserves_backslashes() {
-        let expr = ParserExpr::new(r"name:/\.(mp4|mkv)$/").parse().unwrap();
-        // Should match a literal dot.
-        assert!(expr.eval("/tmp/movie.mp4", 0));
-        // Should not match if the extension is preceded by another character (would match if the '\\.' got stripped).
-        assert!(!expr.eval("/tmp/movieXmp4", 0));
-    }
-
-    #[test]
-    fn regex_literal_can_escape_delimiter_slash() {
-        let expr = ParserExpr::new(r"path:/foo\/bar/").parse().unwrap();
-        assert!(expr.eval("/tmp/foo/bar/baz", 0));
-        assert!(!expr.eval("/tmp/fooXbar/baz", 0));
-    }
-
-    #[test]
-    fn include_filters_are_any_match() {
-        let matcher = Matcher {
-            query: None,
-            include: vec![Regex::new("foo").unwrap(), Regex::new("bar").unwrap()],
-            exclude: vec![],
-        };
-        assert!(matcher.matches_path_str("/tmp/foo.txt", 0));
-        assert!(matcher.matches_path_str("/tmp/bar.txt", 0));
-        assert!(!matcher.matches_path_str("/tmp/baz.txt", 0));
+fn write_html_report(
+    out: &Path,
+    source: String,
+    results: &[SizedPath],
+    records: &[IndexEntry],
+) -> anyhow::Result<()> {
+    let mut tree = tree::DirTree::new();
+    for rec in records {
+        tree.insert(Path::new(&rec.path), rec.size, rec.mtime);
     }
 
-    #[test]
-    fn equal_size_ties_keep_lexicographically_smallest_path() {
-        let mut heap: BinaryHeap<Reverse<SizedPath>> = BinaryHeap::with_capacity(1);
-
-        consider_candidate(
-            &mut heap,
-            1,
-            SizedPath {
-                size: 10,
-                path: PathBuf::from("b"),
-            },
-        );
-        consider_candidate(
-            &mut heap,
-            1,
-            SizedPath {
-                size: 10,
-                path: PathBuf::from("a"),
-            },
-        );
+    let total: u64 = records.iter().map(|r| r.size).sum();
+    let generated = unix_secs(std::time::SystemTime::now()).unwrap_or(0);
+    let provenance = vec![
+        ("Source".to_string(), source),
+        ("Generated".to_string(), format_unix_secs(generated)),
+        (
+            "Tool".to_string(),
+            format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        ),
+        (
+            "Command".to_string(),
+            std::env::args().collect::<Vec<_>>().join(" "),
+        ),
+        (
+            "Matched".to_string(),
+            format!("{} files, {}", records.len(), format_size(total, BINARY)),
+        ),
+    ];
+
+    report::write_html(
+        out,
+        &report::Report {
+            tree: &tree,
+            top: results,
+            records,
+            provenance,
+        },
+    )
+}
 
-        let kept = heap.pop().unwrap().0;
-        assert_eq!(kept.path, PathBuf::from("a"));
+/// Loads every matching file (not just the top N) and hands them to the interactive browser.
+fn run_tui(
+    args: &cli::TuiArgs,
+    matcher: &Matcher,
+    verbose: bool,
+    macros: QueryMacros,
+) -> anyhow::Result<()> {
+    let source = Source::from_args(&args.source);
+    if let Source::Scan { root, .. } = source {
+        eprintln!("Scanning {} ...", root.display());
     }
+    let records = matched_entries(source, matcher, args.filter.min_bytes, verbose)?;
+
+    // Without --within an index has no root to confine actions to, so browse it read-only.
+    let ctx = match source.action_root(&args.safety) {
+        Ok(root) => Some(action_context(
+            &args.safety.deny,
+            args.safety.audit_log.as_deref(),
+            root,
+            actions::Confirm::Interactive,
+        )?),
+        Err(_) => None,
+    };
+    tui::run(records, source.describe(), ctx, macros)
 }
@@ -0,0 +1,278 @@
+//! Mount table (`/proc/self/mountinfo`) and filesystem-type based pruning of the walk.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Kernel and virtual filesystems skipped unless `--fs-types`/`--exclude-fs-types` say otherwise.
+///
+/// `tmpfs` is deliberately not listed: besides `/run` it backs `/tmp` and `/dev/shm` on many
+/// systems, and files there take up memory, so they are worth finding. `/run` itself is small.
+pub const PSEUDO_FS_TYPES: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "sysfs",
+    "tracefs",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    /// `major:minor` of the mounted device
+    pub dev: (u32, u32),
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    /// Device or pseudo-source, e.g. `/dev/sda1` or `proc`
+    pub source: String,
+    pub super_options: String,
+}
+
+impl Mount {
+    /// Directories an overlay mount is assembled from; their contents already appear, merged,
+    /// under the mount point.
+    pub fn overlay_dirs(&self) -> Vec<PathBuf> {
+        if self.fs_type != "overlay" {
+            return Vec::new();
+        }
+        let mut dirs = Vec::new();
+        for opt in self.super_options.split(',') {
+            if let Some(lower) = opt.strip_prefix("lowerdir=") {
+                dirs.extend(
+                    lower
+                        .split(':')
+                        .filter(|d| !d.is_empty())
+                        .map(PathBuf::from),
+                );
+            } else if let Some(dir) = opt
+                .strip_prefix("upperdir=")
+                .or_else(|| opt.strip_prefix("workdir="))
+            {
+                dirs.push(PathBuf::from(dir));
+            }
+        }
+        dirs
+    }
+}
+
+/// The current mount table; empty where `/proc/self/mountinfo` does not exist.
+pub fn read_mountinfo() -> Vec<Mount> {
+    std::fs::read_to_string("/proc/self/mountinfo")
+        .map(|text| parse_mountinfo(&text))
+        .unwrap_or_default()
+}
+
+/// Parses mountinfo lines (see proc(5)):
+/// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+fn parse_mountinfo(text: &str) -> Vec<Mount> {
+    let mut mounts = Vec::new();
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let Some(sep) = fields.iter().position(|f| *f == "-") else {
+            continue;
+        };
+        if sep < 6 || fields.len() < sep + 3 {
+            continue;
+        }
+        let Some((major, minor)) = fields[2].split_once(':') else {
+            continue;
+        };
+        let (Ok(major), Ok(minor)) = (major.parse(), minor.parse()) else {
+            continue;
+        };
+        mounts.push(Mount {
+            dev: (major, minor),
+            mount_point: PathBuf::from(unescape(fields[4])),
+            fs_type: fields[sep + 1].to_string(),
+            source: unescape(fields[sep + 2]),
+            super_options: fields.get(sep + 3).map(|o| unescape(o)).unwrap_or_default(),
+        });
+    }
+    mounts
+}
+
+/// Undoes the kernel's octal escaping of space, tab, newline and backslash (`\040` etc.).
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).filter(|d| {
+            (b'0'..=b'3').contains(&d[0]) && d[1..].iter().all(|b| (b'0'..=b'7').contains(b))
+        });
+        if let (b'\\', Some(d)) = (bytes[i], octal) {
+            out.push((d[0] - b'0') * 64 + (d[1] - b'0') * 8 + (d[2] - b'0'));
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The mount holding `path` (absolute): the longest matching mount point, and of several stacked
+/// on the same point the one mounted last.
+pub fn mount_for<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .filter(|m| path.starts_with(&m.mount_point))
+        .max_by_key(|m| m.mount_point.components().count())
+}
+
+/// Size of a filesystem, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Usage {
+    pub total: u64,
+    pub free: u64,
+    /// Free space usable by unprivileged users
+    pub available: u64,
+}
+
+impl Usage {
+    pub fn used_percent(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (self.total - self.free.min(self.total)) as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// statvfs(3) of the filesystem holding `path`.
+#[cfg(unix)]
+pub fn usage(path: &Path) -> Option<Usage> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out-pointer for the call.
+    let stat = unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(c_path.as_ptr(), &mut stat) != 0 {
+            return None;
+        }
+        stat
+    };
+    let frsize = stat.f_frsize as u64;
+    Some(Usage {
+        total: stat.f_blocks as u64 * frsize,
+        free: stat.f_bfree as u64 * frsize,
+        available: stat.f_bavail as u64 * frsize,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn usage(_path: &Path) -> Option<Usage> {
+    None
+}
+
+/// Mount points (and overlay backing directories) the walk must not enter.
+#[derive(Debug, Default)]
+pub struct MountFilter {
+    skip: HashSet<PathBuf>,
+    mounts: Vec<Mount>,
+}
+
+impl MountFilter {
+    /// `only` keeps just those filesystem types when non-empty. `exclude` replaces the default
+    /// [`PSEUDO_FS_TYPES`] list; overlay backing directories are only skipped by default.
+    pub fn new(mounts: &[Mount], only: &[String], exclude: Option<&[String]>) -> Self {
+        let excluded: Vec<&str> = match exclude {
+            Some(types) => types.iter().map(String::as_str).collect(),
+            None => PSEUDO_FS_TYPES.to_vec(),
+        };
+        let mut skip = HashSet::new();
+        for mount in mounts {
+            let fs_type = mount.fs_type.as_str();
+            let listed = only.is_empty() || only.iter().any(|t| t == fs_type);
+            if !listed || excluded.contains(&fs_type) {
+                skip.insert(mount.mount_point.clone());
+            }
+            if exclude.is_none() {
+                skip.extend(mount.overlay_dirs());
+            }
+        }
+        Self {
+            skip,
+            mounts: mounts.to_vec(),
+        }
+    }
+
+    /// True when `dir` (an absolute path) is a mount point or overlay directory to skip.
+    pub fn skips(&self, dir: &Path) -> bool {
+        self.skip.contains(dir)
+    }
+
+    /// True when the filesystem holding `root` (an absolute path) is one to skip, so a walk
+    /// starting there finds nothing.
+    pub fn skips_root(&self, root: &Path) -> bool {
+        mount_for(&self.mounts, root).is_some_and(|m| self.skip.contains(&m.mount_point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+23 28 0:22 / /proc rw,relatime - proc proc rw
+28 1 254:0 / / rw,relatime - ext4 /dev/vda rw,discard
+29 28 254:16 / /mnt/my\\040disk rw,relatime shared:5 - xfs /dev/vdb rw
+30 28 0:40 / /var/lib/docker/overlay2/m/merged rw - overlay overlay rw,lowerdir=/l/1:/l/2,upperdir=/u,workdir=/w
+";
+
+    #[test]
+    fn parses_mountinfo_with_optional_fields_and_escapes() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[2].mount_point, PathBuf::from("/mnt/my disk"));
+        assert_eq!(mounts[2].fs_type, "xfs");
+        assert_eq!(mounts[2].dev, (254, 16));
+        assert_eq!(
+            mounts[3].overlay_dirs(),
+            ["/l/1", "/l/2", "/u", "/w"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn default_filter_skips_pseudo_filesystems_and_overlay_dirs() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let filter = MountFilter::new(&mounts, &[], None);
+        assert!(filter.skips(Path::new("/proc")));
+        assert!(filter.skips(Path::new("/l/2")));
+        assert!(!filter.skips(Path::new("/")));
+        assert!(!filter.skips(Path::new("/mnt/my disk")));
+
+        let only_ext4 = MountFilter::new(&mounts, &["ext4".to_string()], Some(&[]));
+        assert!(only_ext4.skips(Path::new("/mnt/my disk")));
+        assert!(!only_ext4.skips(Path::new("/")));
+        assert!(!only_ext4.skips(Path::new("/l/1")));
+
+        let only_xfs = MountFilter::new(&mounts, &["xfs".to_string()], None);
+        assert!(only_xfs.skips_root(Path::new("/home")));
+        assert!(!only_xfs.skips_root(Path::new("/mnt/my disk/photos")));
+        assert!(!MountFilter::default().skips_root(Path::new("/")));
+    }
+
+    #[test]
+    fn mount_for_picks_the_deepest_mount_point() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let mount = |p: &str| mount_for(&mounts, Path::new(p)).map(|m| m.fs_type.as_str());
+        assert_eq!(mount("/mnt/my disk/a.iso"), Some("xfs"));
+        assert_eq!(mount("/mnt/other/a.iso"), Some("ext4"));
+        assert_eq!(mount("/proc/kcore"), Some("proc"));
+    }
+}
@@ -0,0 +1,640 @@
+//! The query engine: which files count, from include/exclude regexes and a query expression.
+//!
+//! Expressions combine predicates with `AND`/`&&`, `OR`/`||`, `NOT`/`!` and parentheses:
+//! `name:/re/`, `path:/re/` (or a bare `/re/`), `ext:iso`, `ext in (mkv, mp4)` and
+//! `size>1GB` (also `>=`, `<`, `<=`, `=`). `@name` expands a named fragment from [`QueryMacros`].
+
+use crate::{Error, Result};
+use regex::{Regex, RegexBuilder};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Decides whether a file counts: no exclude pattern matches its path, some include pattern does
+/// (when there are any), and the query expression holds.
+#[derive(Debug, Clone, Default)]
+pub struct Matcher {
+    query: Option<Expr>,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl Matcher {
+    /// Matches every file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_query(mut self, query: Expr) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    /// Adds a case-insensitive regex the path must match; any one of them will do.
+    pub fn include(mut self, pattern: &str) -> Result<Self> {
+        self.include.push(case_insensitive(pattern)?);
+        Ok(self)
+    }
+
+    /// Adds a case-insensitive regex that rules out the paths it matches.
+    pub fn exclude(mut self, pattern: &str) -> Result<Self> {
+        self.exclude.push(case_insensitive(pattern)?);
+        Ok(self)
+    }
+
+    /// True when an exclude pattern matches everything below `dir`, so the walk can skip it.
+    ///
+    /// A pattern qualifies when it matches within `dir/` itself, as `/node_modules/` or `^/proc`
+    /// do, whatever name follows; patterns that need to see the file name, such as `\.log$` or
+    /// `/logs/[^.]*$`, do not.
+    pub fn excludes_dir(&self, dir: &str) -> bool {
+        // A match ending inside `dir/` holds for every path below it, as long as the assertions
+        // at its end (`\b`, `$`, ...) do not depend on the next character. These cover each kind
+        // of character those assertions tell apart.
+        const NEXT: [&str; 5] = ["a", "é", ".", "\n", "\r"];
+        let slash = format!("{}/", dir.trim_end_matches('/'));
+        self.exclude.iter().any(|re| {
+            NEXT.iter().all(|next| {
+                re.shortest_match(&format!("{slash}{next}"))
+                    .is_some_and(|end| end <= slash.len())
+            })
+        })
+    }
+
+    pub fn matches(&self, path: &str, size: u64) -> bool {
+        for re in &self.exclude {
+            if re.is_match(path) {
+                return false;
+            }
+        }
+
+        // If any include filters are provided, the path must match at least one.
+        if !self.include.is_empty() && !self.include.iter().any(|re| re.is_match(path)) {
+            return false;
+        }
+
+        if let Some(expr) = &self.query {
+            return expr.eval(path, size);
+        }
+
+        true
+    }
+}
+
+fn case_insensitive(pattern: &str) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|source| Error::Regex {
+            pattern: pattern.to_string(),
+            source,
+        })
+}
+
+/// A parsed query expression.
+#[derive(Debug, Clone)]
+pub struct Expr(Node);
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Pred(Predicate),
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self> {
+        Self::parse_with_macros(source, &NO_MACROS)
+    }
+
+    /// Parses `source`, expanding `@name` references from `macros`.
+    pub fn parse_with_macros(source: &str, macros: &QueryMacros) -> Result<Self> {
+        ParserExpr::with_macros(source, macros).parse().map(Expr)
+    }
+
+    /// `size OP bytes`, without going through the parser.
+    pub fn size(op: CmpOp, bytes: u64) -> Self {
+        Expr(Node::Pred(Predicate::SizeCmp { op, bytes }))
+    }
+
+    pub fn eval(&self, path: &str, size: u64) -> bool {
+        self.0.eval(path, size)
+    }
+}
+
+impl Node {
+    fn eval(&self, path: &str, size: u64) -> bool {
+        match self {
+            Node::And(a, b) => a.eval(path, size) && b.eval(path, size),
+            Node::Or(a, b) => a.eval(path, size) || b.eval(path, size),
+            Node::Not(inner) => !inner.eval(path, size),
+            Node::Pred(p) => p.eval(path, size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CmpOp {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+}
+
+impl CmpOp {
+    pub fn holds(&self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Lte => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Gte => lhs >= rhs,
+            CmpOp::Eq => lhs == rhs,
+        }
+    }
+
+    /// Splits a leading `>=`, `<=`, `>`, `<` or `=` off `s`.
+    pub fn split_prefix(s: &str) -> Option<(CmpOp, &str)> {
+        [
+            (">=", CmpOp::Gte),
+            ("<=", CmpOp::Lte),
+            (">", CmpOp::Gt),
+            ("<", CmpOp::Lt),
+            ("=", CmpOp::Eq),
+        ]
+        .into_iter()
+        .find_map(|(token, op)| s.strip_prefix(token).map(|rest| (op, rest)))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    PathRegex(Regex),
+    NameRegex(Regex),
+    ExtEq(String),
+    ExtIn(Vec<String>),
+    SizeCmp { op: CmpOp, bytes: u64 },
+}
+
+impl Predicate {
+    fn eval(&self, path: &str, size: u64) -> bool {
+        match self {
+            Predicate::PathRegex(re) => re.is_match(path),
+            Predicate::NameRegex(re) => Path::new(path)
+                .file_name()
+                .and_then(|s| s.to_str())
+                .is_some_and(|name| re.is_match(name)),
+            Predicate::ExtEq(ext) => Path::new(path)
+                .extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|e| e.eq_ignore_ascii_case(ext)),
+            Predicate::ExtIn(exts) => Path::new(path)
+                .extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|e| exts.iter().any(|ext| e.eq_ignore_ascii_case(ext))),
+            Predicate::SizeCmp { op, bytes } => op.holds(size, *bytes),
+        }
+    }
+}
+
+/// Named query fragments, referenced as `@name` in expressions.
+pub type QueryMacros = BTreeMap<String, String>;
+
+static NO_MACROS: QueryMacros = BTreeMap::new();
+
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err(Error::Query(format!($($arg)*)))
+    };
+}
+
+struct ParserExpr<'a> {
+    s: &'a str,
+    i: usize,
+    macros: &'a QueryMacros,
+    /// Macros being expanded, outermost first, to reject recursive definitions
+    expanding: Vec<&'a str>,
+}
+
+impl<'a> ParserExpr<'a> {
+    fn with_macros(s: &'a str, macros: &'a QueryMacros) -> Self {
+        Self {
+            s,
+            i: 0,
+            macros,
+            expanding: Vec::new(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Node> {
+        let expr = self.parse_or()?;
+        self.skip_ws();
+        if self.i != self.s.len() {
+            bail!("Unexpected trailing input at byte {}", self.i);
+        }
+        Ok(expr)
+    }
+
+    fn parse_or(&mut self) -> Result<Node> {
+        let mut left = self.parse_and()?;
+        loop {
+            self.skip_ws();
+            if self.consume_op("||") || self.consume_kw("OR") {
+                let right = self.parse_and()?;
+                left = Node::Or(Box::new(left), Box::new(right));
+            } else {
+                break;
+            }
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Node> {
+        let mut left = self.parse_unary()?;
+        loop {
+            self.skip_ws();
+            if self.consume_op("&&") || self.consume_kw("AND") {
+                let right = self.parse_unary()?;
+                left = Node::And(Box::new(left), Box::new(right));
+            } else {
+                break;
+            }
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node> {
+        self.skip_ws();
+        if self.consume_op("!") || self.consume_kw("NOT") {
+            let inner = self.parse_unary()?;
+            return Ok(Node::Not(Box::new(inner)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node> {
+        self.skip_ws();
+        if self.consume_op("(") {
+            let inner = self.parse_or()?;
+            self.skip_ws();
+            if !self.consume_op(")") {
+                bail!("Expected ')' at byte {}", self.i);
+            }
+            return Ok(inner);
+        }
+
+        if self.peek_char() == Some('@') {
+            return self.parse_macro();
+        }
+
+        let pred = self.parse_predicate()?;
+        Ok(Node::Pred(pred))
+    }
+
+    /// Parses the definition of the `@name` macro at the cursor. Errors inside it are reported at
+    /// their position in the definition.
+    fn parse_macro(&mut self) -> Result<Node> {
+        let at = self.i;
+        self.i += 1; // skip '@'
+        let rest = &self.s[self.i..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(rest.len());
+        let name = &rest[..len];
+        self.i += len;
+        if name.is_empty() {
+            bail!("Expected macro name after '@' at byte {at}");
+        }
+
+        let Some((name, source)) = self.macros.get_key_value(name) else {
+            bail!("Unknown query macro @{name} at byte {at}");
+        };
+        if self.expanding.contains(&name.as_str()) {
+            let chain: Vec<String> = self.expanding.iter().map(|m| format!("@{m}")).collect();
+            bail!(
+                "Recursive query macro at byte {at}: {} -> @{name}",
+                chain.join(" -> ")
+            );
+        }
+
+        let mut inner = ParserExpr::with_macros(source, self.macros);
+        inner.expanding = self.expanding.clone();
+        inner.expanding.push(name);
+        inner.parse().map_err(|err| Error::Macro {
+            name: name.clone(),
+            definition: source.clone(),
+            at,
+            source: Box::new(err),
+        })
+    }
+
+    fn parse_predicate(&mut self) -> Result<Predicate> {
+        self.skip_ws();
+
+        if self.peek_char() == Some('/') {
+            let re = self.parse_regex_literal()?;
+            return Ok(Predicate::PathRegex(re));
+        }
+
+        if self.consume_kw("name") {
+            self.expect_char(':')?;
+            let re = self.parse_regex_literal()?;
+            return Ok(Predicate::NameRegex(re));
+        }
+
+        if self.consume_kw("path") {
+            self.expect_char(':')?;
+            let re = self.parse_regex_literal()?;
+            return Ok(Predicate::PathRegex(re));
+        }
+
+        if self.consume_kw("ext") {
+            if self.consume_kw("in") {
+                return Ok(Predicate::ExtIn(self.parse_list()?));
+            }
+            self.expect_char(':')?;
+            let val = self.parse_bare_value();
+            if val.is_empty() {
+                bail!("ext: requires a value");
+            }
+            return Ok(Predicate::ExtEq(val));
+        }
+
+        if self.consume_kw("size") {
+            let op = self.parse_cmp_op()?;
+            self.skip_ws();
+            let at = self.i;
+            let val = self.parse_bare_value();
+            let bytes = match parse_size_bytes(&val) {
+                Ok(bytes) => bytes,
+                Err(err) => bail!("Invalid size literal at byte {at}: {val} ({err})"),
+            };
+            return Ok(Predicate::SizeCmp { op, bytes });
+        }
+
+        bail!("Expected predicate at byte {}", self.i)
+    }
+
+    fn parse_cmp_op(&mut self) -> Result<CmpOp> {
+        self.skip_ws();
+        let rest = &self.s[self.i..];
+        let Some((op, after)) = CmpOp::split_prefix(rest) else {
+            bail!("Expected comparison operator after size at byte {}", self.i)
+        };
+        self.i += rest.len() - after.len();
+        Ok(op)
+    }
+
+    fn parse_regex_literal(&mut self) -> Result<Regex> {
+        self.skip_ws();
+        if self.peek_char() != Some('/') {
+            bail!(
+                "Expected regex literal starting with '/' at byte {}",
+                self.i
+            );
+        }
+        self.i += 1; // skip '/'
+
+        let mut pat = String::new();
+        // We treat '/' as the delimiter; a '/' can be included by escaping it as '\/'.
+        // All other backslashes must be preserved (e.g. /\./, /\d+/) so the regex semantics stay intact.
+        while self.i < self.s.len() {
+            let rest = &self.s[self.i..];
+            let Some(c) = rest.chars().next() else {
+                break;
+            };
+
+            if c == '/' {
+                self.i += 1;
+                return Regex::new(&pat).map_err(|source| Error::Regex {
+                    pattern: format!("/{pat}/"),
+                    source,
+                });
+            }
+
+            if c == '\\' {
+                // Preserve backslashes, but allow escaping the delimiter.
+                self.i += 1;
+                if self.i >= self.s.len() {
+                    bail!("Unterminated escape in regex literal");
+                }
+                let rest2 = &self.s[self.i..];
+                let next = rest2.chars().next().unwrap();
+                self.i += next.len_utf8();
+                if next == '/' {
+                    pat.push('/');
+                } else {
+                    pat.push('\\');
+                    pat.push(next);
+                }
+                continue;
+            }
+
+            self.i += c.len_utf8();
+            pat.push(c);
+        }
+
+        bail!("Unterminated regex literal")
+    }
+
+    /// `(a, b, c)`
+    fn parse_list(&mut self) -> Result<Vec<String>> {
+        self.expect_char('(')?;
+        let start = self.i;
+        let Some(len) = self.s[start..].find(')') else {
+            bail!("Expected ')' to close the list at byte {start}");
+        };
+        self.i += len + 1;
+        let items: Vec<String> = self.s[start..start + len]
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .collect();
+        if items.iter().any(String::is_empty) {
+            bail!("Empty item in list at byte {start}");
+        }
+        Ok(items)
+    }
+
+    fn parse_bare_value(&mut self) -> String {
+        self.skip_ws();
+        let start = self.i;
+        while let Some(c) = self.peek_char() {
+            if c.is_whitespace() || c == ')' || c == '(' {
+                break;
+            }
+            self.i += c.len_utf8();
+        }
+        self.s[start..self.i].to_string()
+    }
+
+    fn expect_char(&mut self, expected: char) -> Result<()> {
+        self.skip_ws();
+        if self.peek_char() == Some(expected) {
+            self.i += expected.len_utf8();
+            Ok(())
+        } else {
+            bail!("Expected '{expected}' at byte {}", self.i)
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek_char() {
+            if c.is_whitespace() {
+                self.i += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.s[self.i..].chars().next()
+    }
+
+    fn consume_op(&mut self, op: &str) -> bool {
+        if self.s[self.i..].starts_with(op) {
+            self.i += op.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_kw(&mut self, kw: &str) -> bool {
+        self.skip_ws();
+        let rest = &self.s[self.i..];
+        let mut taken = String::new();
+        for c in rest.chars() {
+            if c.is_alphanumeric() || c == '_' {
+                taken.push(c);
+            } else {
+                break;
+            }
+        }
+        if taken.eq_ignore_ascii_case(kw) {
+            self.i += taken.len();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Parses sizes such as `512`, `10KB` or `3 GiB`; units are binary (`1KB` = 1024 bytes).
+pub fn parse_size_bytes(s: &str) -> Result<u64> {
+    let t = s.trim();
+    if t.is_empty() {
+        return Err(Error::Size("empty size".to_string()));
+    }
+
+    let mut digits_end = 0;
+    for (idx, ch) in t.char_indices() {
+        if ch.is_ascii_digit() {
+            digits_end = idx + 1;
+        } else {
+            break;
+        }
+    }
+
+    if digits_end == 0 {
+        return Err(Error::Size("size must start with digits".to_string()));
+    }
+
+    let num: u64 = t[..digits_end]
+        .parse()
+        .map_err(|e| Error::Size(format!("{e}")))?;
+    let unit = t[digits_end..].trim().to_ascii_lowercase();
+    let mul: u64 = match unit.as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024_u64.pow(2),
+        "g" | "gb" | "gib" => 1024_u64.pow(3),
+        "t" | "tb" | "tib" => 1024_u64.pow(4),
+        _ => return Err(Error::Size(format!("unknown size unit: {unit}"))),
+    };
+    num.checked_mul(mul)
+        .ok_or_else(|| Error::Size("size overflow".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_literal_preserves_backslashes() {
+        let expr = Expr::parse(r"name:/\.(mp4|mkv)$/").unwrap();
+        // Should match a literal dot.
+        assert!(expr.eval("/tmp/movie.mp4", 0));
+        // Should not match if the extension is preceded by another character (would match if the '\\.' got stripped).
+        assert!(!expr.eval("/tmp/movieXmp4", 0));
+    }
+
+    #[test]
+    fn regex_literal_can_escape_delimiter_slash() {
+        let expr = Expr::parse(r"path:/foo\/bar/").unwrap();
+        assert!(expr.eval("/tmp/foo/bar/baz", 0));
+        assert!(!expr.eval("/tmp/fooXbar/baz", 0));
+    }
+
+    #[test]
+    fn query_macros_expand_and_report_errors_in_their_definition() {
+        let macros: QueryMacros = [
+            ("video", "ext in (mp4, MKV)"),
+            ("big-video", "@video AND size>1GB"),
+            ("broken", "ext:iso AND size>>1"),
+            ("loop", "ext:a OR @again"),
+            ("again", "@loop"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let parse = |s| Expr::parse_with_macros(s, &macros);
+        // The error and its causes, as `{:#}` on an `anyhow::Error` shows them.
+        let chain = |err: Error| {
+            let mut msg = err.to_string();
+            let mut source = std::error::Error::source(&err);
+            while let Some(err) = source {
+                msg.push_str(&format!(": {err}"));
+                source = err.source();
+            }
+            msg
+        };
+
+        let expr = parse("@big-video OR name:/^keep/").unwrap();
+        assert!(expr.eval("/m/a.mkv", 2 << 30));
+        assert!(!expr.eval("/m/a.mkv", 1 << 20));
+        assert!(expr.eval("/m/keep.txt", 0));
+
+        let err = chain(parse("size>1 AND @broken").unwrap_err());
+        assert!(
+            err.contains("@broken") && err.contains("at byte 11") && err.contains("at byte 17")
+        );
+        let err = chain(parse("@loop").unwrap_err());
+        assert!(err.contains("@loop -> @again -> @loop"), "{err}");
+        assert!(parse("@missing").is_err());
+    }
+
+    #[test]
+    fn include_filters_are_any_match() {
+        let matcher = Matcher::new()
+            .include("foo")
+            .unwrap()
+            .include("BAR")
+            .unwrap();
+        assert!(matcher.matches("/tmp/foo.txt", 0));
+        assert!(matcher.matches("/tmp/bar.txt", 0));
+        assert!(!matcher.matches("/tmp/baz.txt", 0));
+    }
+
+    #[test]
+    fn only_directory_patterns_prune_subtrees() {
+        let exclude = |pat: &str| Matcher::new().exclude(pat).unwrap();
+        assert!(exclude("/node_modules/").excludes_dir("/src/app/node_modules"));
+        assert!(exclude("^/proc").excludes_dir("/proc"));
+        assert!(!exclude("^/proc").excludes_dir("/home/proc"));
+        assert!(!exclude(r"\.log$").excludes_dir("/var/log"));
+        assert!(!exclude("/cache/[^/]+$").excludes_dir("/home/cache"));
+        assert!(!exclude("/logs/[^.]*$").excludes_dir("/var/logs"));
+        assert!(!exclude(r"/tmp/\b").excludes_dir("/tmp"));
+    }
+}
@@ -0,0 +1,398 @@
+//! Ranking of matched files and the top-N collector.
+
+use crate::index::{format_unix_secs, unix_secs, IndexEntry};
+use humansize::{format_size, BINARY};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SortKey {
+    Size,
+    Mtime,
+    Atime,
+    Path,
+    NameLength,
+    Depth,
+    Score,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AgeField {
+    Mtime,
+    Atime,
+}
+
+/// A ranked file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SizedPath {
+    pub size: u64,
+    pub path: PathBuf,
+    rank: Rank,
+    /// Compressed size, for archive members
+    pub packed: Option<u64>,
+}
+
+/// Value a candidate is ranked by; every candidate in one heap uses the same variant.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum SortValue {
+    Num(u64),
+    Time(i64),
+    Path(PathBuf),
+}
+
+/// Direction-aware rank so the heap ordering stays a plain `Ord` regardless of `--reverse`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum Rank {
+    Desc(SortValue),
+    Asc(Reverse<SortValue>),
+}
+
+/// What files are ranked by, and in which direction.
+#[derive(Debug, Clone, Copy)]
+pub struct Ranking {
+    key: SortKey,
+    reverse: bool,
+    score: ScoreFormula,
+}
+
+/// "Biggest waste" score: `size * days_since(age) ^ exponent`.
+#[derive(Debug, Clone, Copy)]
+struct ScoreFormula {
+    age: AgeField,
+    exponent: f64,
+    /// Reference time the age is measured from, seconds since the Unix epoch
+    now: i64,
+}
+
+impl ScoreFormula {
+    fn score(&self, size: u64, mtime: Option<i64>, atime: Option<i64>) -> u64 {
+        let stamp = match self.age {
+            AgeField::Mtime => mtime,
+            AgeField::Atime => atime,
+        };
+        // Unknown or future timestamps count as "just touched".
+        let days = stamp
+            .map(|t| self.now.saturating_sub(t).max(0) as f64 / 86_400.0)
+            .unwrap_or(0.0);
+        // Float-to-int casts saturate, so absurd ages cannot overflow.
+        (size as f64 * days.powf(self.exponent)) as u64
+    }
+}
+
+impl Ranking {
+    /// Highest `key` first, or lowest with `reverse`; [`SortKey::Path`] goes the other way, A to
+    /// Z unless reversed. [`SortKey::Score`] weighs size by days since the last access; see
+    /// [`Ranking::with_score`].
+    pub fn new(key: SortKey, reverse: bool) -> Self {
+        Self {
+            key,
+            reverse,
+            score: ScoreFormula {
+                age: AgeField::Atime,
+                exponent: 1.0,
+                now: unix_secs(std::time::SystemTime::now()).unwrap_or(0),
+            },
+        }
+    }
+
+    /// Largest first.
+    pub fn by_size() -> Self {
+        Self::new(SortKey::Size, false)
+    }
+
+    /// Scores by `size * days_since(age) ^ exponent`. `exponent` should be finite and
+    /// non-negative; a negative one ranks the freshest files first.
+    pub fn with_score(mut self, age: AgeField, exponent: f64) -> Self {
+        self.score.age = age;
+        self.score.exponent = exponent;
+        self
+    }
+
+    pub fn candidate(
+        &self,
+        path: PathBuf,
+        size: u64,
+        mtime: Option<i64>,
+        atime: Option<i64>,
+    ) -> SizedPath {
+        // Files with unknown timestamps (e.g. old indexes) always rank last.
+        let unknown_time = if self.reverse { i64::MAX } else { i64::MIN };
+        let value = match self.key {
+            SortKey::Size => SortValue::Num(size),
+            SortKey::Mtime => SortValue::Time(mtime.unwrap_or(unknown_time)),
+            SortKey::Atime => SortValue::Time(atime.unwrap_or(unknown_time)),
+            SortKey::Path => SortValue::Path(path.clone()),
+            SortKey::NameLength => SortValue::Num(
+                path.file_name()
+                    .map(|n| n.to_string_lossy().chars().count() as u64)
+                    .unwrap_or(0),
+            ),
+            SortKey::Depth => SortValue::Num(path.components().count() as u64),
+            SortKey::Score => SortValue::Num(self.score.score(size, mtime, atime)),
+        };
+        let ascending = self.reverse != (self.key == SortKey::Path);
+        let rank = if ascending {
+            Rank::Asc(Reverse(value))
+        } else {
+            Rank::Desc(value)
+        };
+        SizedPath {
+            size,
+            path,
+            rank,
+            packed: None,
+        }
+    }
+
+    /// The value `file` is ranked by, for display: `1.5 GiB (1610612736 bytes)` by size,
+    /// `mtime 2024-05-01 12:00:00 UTC`, `depth 7`, ...
+    pub fn describe(&self, file: &SizedPath) -> String {
+        let name = match self.key {
+            SortKey::Size => {
+                return format!("{} ({} bytes)", format_size(file.size, BINARY), file.size)
+            }
+            SortKey::Mtime => "mtime",
+            SortKey::Atime => "atime",
+            SortKey::Path => "path",
+            SortKey::NameLength => "name length",
+            SortKey::Depth => "depth",
+            SortKey::Score => "score",
+        };
+        let (Rank::Desc(value) | Rank::Asc(Reverse(value))) = &file.rank;
+        match value {
+            SortValue::Time(t) if *t == i64::MIN || *t == i64::MAX => format!("{name} unknown"),
+            SortValue::Time(t) => format!("{name} {}", format_unix_secs(*t)),
+            SortValue::Path(path) => format!("{name} {}", path.display()),
+            SortValue::Num(n) => format!("{name} {n}"),
+        }
+    }
+}
+
+impl Ord for SizedPath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Natural (max-heap) ordering: higher rank first.
+        // Tie-breaker ensures deterministic ordering.
+        self.rank
+            .cmp(&other.rank)
+            // Reverse path ordering so that, for equal ranks, lexicographically *smaller* paths win
+            // when we maintain a min-heap via Reverse<SizedPath>.
+            .then_with(|| other.path.cmp(&self.path))
+    }
+}
+
+impl PartialOrd for SizedPath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Keeps the `n` best-ranked files offered to it.
+#[derive(Debug, Clone)]
+pub struct TopN {
+    n: usize,
+    ranking: Ranking,
+    heap: BinaryHeap<Reverse<SizedPath>>,
+}
+
+impl TopN {
+    /// `n` is at least 1.
+    pub fn new(n: usize, ranking: Ranking) -> Self {
+        let n = n.max(1);
+        Self {
+            n,
+            ranking,
+            heap: BinaryHeap::with_capacity(n),
+        }
+    }
+
+    pub fn ranking(&self) -> &Ranking {
+        &self.ranking
+    }
+
+    /// Ranks `entry` and keeps it if it is among the best `n` so far.
+    pub fn offer(&mut self, entry: &IndexEntry) {
+        self.offer_at(PathBuf::from(&entry.path), entry);
+    }
+
+    /// Like [`TopN::offer`], for a file whose real `path` is not valid UTF-8 and so differs from
+    /// the lossy `entry.path`.
+    pub fn offer_at(&mut self, path: PathBuf, entry: &IndexEntry) {
+        let mut candidate = self
+            .ranking
+            .candidate(path, entry.size, entry.mtime, entry.atime);
+        candidate.packed = entry.packed;
+        self.offer_candidate(candidate);
+    }
+
+    /// Keeps `candidate`, ranked with [`TopN::ranking`], if it is among the best `n` so far.
+    pub fn offer_candidate(&mut self, candidate: SizedPath) {
+        consider_candidate(&mut self.heap, self.n, candidate);
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// The lowest-ranked file kept, which the next one has to beat once `n` are kept.
+    pub fn floor(&self) -> Option<&SizedPath> {
+        self.heap.peek().map(|Reverse(sp)| sp)
+    }
+
+    /// Best-ranked first; equal ranks by ascending path.
+    pub fn into_sorted_vec(self) -> Vec<SizedPath> {
+        let mut results: Vec<SizedPath> = self.heap.into_iter().map(|Reverse(sp)| sp).collect();
+        results.sort_by(|a, b| b.cmp(a));
+        results
+    }
+}
+
+fn consider_candidate(
+    top_files: &mut BinaryHeap<Reverse<SizedPath>>,
+    top_n: usize,
+    candidate: SizedPath,
+) {
+    if top_files.len() < top_n {
+        top_files.push(Reverse(candidate));
+    } else if let Some(Reverse(current_smallest)) = top_files.peek() {
+        if candidate.cmp(current_smallest) == Ordering::Greater {
+            top_files.pop();
+            top_files.push(Reverse(candidate));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn ranking(key: SortKey, reverse: bool) -> Ranking {
+        Ranking {
+            key,
+            reverse,
+            score: ScoreFormula {
+                age: AgeField::Atime,
+                exponent: 1.0,
+                now: 1_000 * 86_400,
+            },
+        }
+    }
+
+    fn by_size() -> Ranking {
+        ranking(SortKey::Size, false)
+    }
+
+    #[test]
+    fn equal_size_ties_keep_lexicographically_smallest_path() {
+        let mut heap: BinaryHeap<Reverse<SizedPath>> = BinaryHeap::with_capacity(1);
+
+        consider_candidate(
+            &mut heap,
+            1,
+            by_size().candidate(PathBuf::from("b"), 10, None, None),
+        );
+        consider_candidate(
+            &mut heap,
+            1,
+            by_size().candidate(PathBuf::from("a"), 10, None, None),
+        );
+
+        let kept = heap.pop().unwrap().0;
+        assert_eq!(kept.path, PathBuf::from("a"));
+    }
+
+    #[test]
+    fn reverse_size_keeps_smallest_files() {
+        let ranking = ranking(SortKey::Size, true);
+        let mut heap: BinaryHeap<Reverse<SizedPath>> = BinaryHeap::with_capacity(2);
+        for (path, size) in [("a", 30), ("b", 10), ("c", 20), ("d", 5)] {
+            consider_candidate(
+                &mut heap,
+                2,
+                ranking.candidate(PathBuf::from(path), size, None, None),
+            );
+        }
+
+        let mut kept: Vec<SizedPath> = heap.into_iter().map(|Reverse(sp)| sp).collect();
+        kept.sort_by(|a, b| b.cmp(a));
+        let sizes: Vec<u64> = kept.iter().map(|sp| sp.size).collect();
+        assert_eq!(sizes, vec![5, 10]);
+    }
+
+    #[test]
+    fn oldest_first_ranks_unknown_mtime_last() {
+        let ranking = ranking(SortKey::Mtime, true);
+        let mut heap: BinaryHeap<Reverse<SizedPath>> = BinaryHeap::with_capacity(2);
+        consider_candidate(
+            &mut heap,
+            2,
+            ranking.candidate(PathBuf::from("new"), 1, Some(2_000), None),
+        );
+        consider_candidate(
+            &mut heap,
+            2,
+            ranking.candidate(PathBuf::from("unknown"), 1, None, None),
+        );
+        consider_candidate(
+            &mut heap,
+            2,
+            ranking.candidate(PathBuf::from("old"), 1, Some(1_000), None),
+        );
+
+        let mut kept: Vec<SizedPath> = heap.into_iter().map(|Reverse(sp)| sp).collect();
+        kept.sort_by(|a, b| b.cmp(a));
+        let paths: Vec<&Path> = kept.iter().map(|sp| sp.path.as_path()).collect();
+        assert_eq!(paths, vec![Path::new("old"), Path::new("new")]);
+    }
+
+    #[test]
+    fn score_prefers_stale_files_over_fresh_large_ones() {
+        const GB: u64 = 1024 * 1024 * 1024;
+        let ranking = ranking(SortKey::Score, false);
+        let now = ranking.score.now;
+        let fresh = ranking.candidate(PathBuf::from("fresh"), 10 * GB, None, Some(now - 86_400));
+        let stale = ranking.candidate(
+            PathBuf::from("stale"),
+            5 * GB,
+            None,
+            Some(now - 3 * 365 * 86_400),
+        );
+        assert!(stale > fresh);
+
+        let unknown = ranking.candidate(PathBuf::from("unknown"), 100 * GB, None, None);
+        assert!(fresh > unknown);
+    }
+
+    #[test]
+    fn paths_rank_a_to_z_unless_reversed() {
+        for (reverse, expected) in [(false, ["a", "b"]), (true, ["c", "b"])] {
+            let mut top = TopN::new(2, ranking(SortKey::Path, reverse));
+            for path in ["b", "c", "a"] {
+                top.offer(&IndexEntry::new(path, 1));
+            }
+            let floor = top.ranking().describe(top.floor().unwrap());
+            assert_eq!(floor, format!("path {}", expected[1]));
+            let paths: Vec<PathBuf> = top
+                .into_sorted_vec()
+                .into_iter()
+                .map(|sp| sp.path)
+                .collect();
+            assert_eq!(paths, expected.map(PathBuf::from));
+        }
+    }
+
+    #[test]
+    fn describe_shows_the_sort_key_of_a_file() {
+        let file = |key| ranking(key, false).candidate(PathBuf::from("/a/b"), 2048, Some(0), None);
+        let describe = |key| ranking(key, false).describe(&file(key));
+        assert_eq!(describe(SortKey::Size), "2 KiB (2048 bytes)");
+        assert_eq!(describe(SortKey::Mtime), "mtime 1970-01-01 00:00:00 UTC");
+        assert_eq!(describe(SortKey::Atime), "atime unknown");
+        assert_eq!(describe(SortKey::Depth), "depth 3");
+    }
+}
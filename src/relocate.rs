@@ -0,0 +1,293 @@
+//! Move files under another directory (usually another volume), keeping their path relative to
+//! the scan root.
+//!
+//! Cross-device moves copy to `NAME.lff-partial`, verify size and SHA-256, then rename into place
+//! before the original is removed, so an interrupted run can simply be repeated: a finished copy
+//! whose content matches the original is reused and leftover partial files are overwritten.
+
+use crate::checksum::{hash_file, same_content};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub struct Relocator {
+    root: PathBuf,
+    dest: PathBuf,
+    leave_symlink: bool,
+}
+
+impl Relocator {
+    /// `root` must be the resolved scan root, as used by [`crate::actions::Guard`]. `dest` is only
+    /// created once the first file is moved, so dry runs leave no trace.
+    pub fn new(root: &Path, dest: &Path, leave_symlink: bool) -> anyhow::Result<Self> {
+        let dest = std::path::absolute(dest)
+            .with_context(|| format!("Failed to resolve {}", dest.display()))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            dest,
+            leave_symlink,
+        })
+    }
+
+    /// Moves `src` to its mirrored location and returns it.
+    pub fn relocate(&self, src: &Path) -> anyhow::Result<Option<PathBuf>> {
+        let rel = src
+            .strip_prefix(&self.root)
+            .with_context(|| format!("{} is outside the scan root", src.display()))?;
+        let target = self.dest.join(rel);
+        if target == src {
+            anyhow::bail!("target is the file itself");
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        if target.symlink_metadata().is_ok() {
+            // Left behind by an interrupted run: only reuse it if it is a faithful copy.
+            if !same_content(src, &target)? {
+                anyhow::bail!("{} already exists with different content", target.display());
+            }
+            remove_original(src)?;
+        } else {
+            match std::fs::rename(src, &target) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                    copy_verified(src, &target)?;
+                    remove_original(src)?;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to move {} to {}", src.display(), target.display())
+                    });
+                }
+            }
+        }
+
+        if self.leave_symlink {
+            link_back(&target, src)?;
+        }
+        Ok(Some(target))
+    }
+}
+
+/// Copies through a partial file, preserving metadata, and renames it into place once verified.
+fn copy_verified(src: &Path, target: &Path) -> anyhow::Result<()> {
+    let src_md = std::fs::metadata(src)?;
+    let input = File::open(src).with_context(|| format!("Failed to open {}", src.display()))?;
+    copy_verified_from(input, &src_md, target)
+        .with_context(|| format!("Failed to copy {}", src.display()))
+}
+
+/// Copies `input` (with metadata `src_md`) to `target` via a partial file that is removed
+/// whenever the copy fails.
+fn copy_verified_from(
+    mut input: impl Read,
+    src_md: &std::fs::Metadata,
+    target: &Path,
+) -> anyhow::Result<()> {
+    let mut partial_name = target
+        .file_name()
+        .context("Target has no file name")?
+        .to_os_string();
+    partial_name.push(".lff-partial");
+    let partial = target.with_file_name(partial_name);
+
+    let placed = (|| {
+        let mut output = File::create(&partial)
+            .with_context(|| format!("Failed to create {}", partial.display()))?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 1 << 20];
+        loop {
+            let n = input.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            output.write_all(&buf[..n])?;
+        }
+        output.sync_all()?;
+        drop(output);
+        let src_digest = hasher.finalize();
+
+        let copied_len = std::fs::metadata(&partial)?.len();
+        if copied_len != src_md.len() || hash_file(&partial)? != src_digest.as_slice() {
+            anyhow::bail!("verification of the copy failed");
+        }
+
+        preserve_metadata(src_md, &partial)?;
+        std::fs::rename(&partial, target)
+            .with_context(|| format!("Failed to move {} into place", partial.display()))
+    })();
+    if placed.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    placed
+}
+
+/// Copies timestamps, permissions and (best-effort) ownership from `src_md` onto `path`.
+pub fn preserve_metadata(src_md: &std::fs::Metadata, path: &Path) -> anyhow::Result<()> {
+    let mut times = std::fs::FileTimes::new().set_modified(src_md.modified()?);
+    if let Ok(atime) = src_md.accessed() {
+        times = times.set_accessed(atime);
+    }
+    // Times first: once a read-only mode is copied, the file can no longer be opened for writing.
+    File::options().write(true).open(path)?.set_times(times)?;
+    std::fs::set_permissions(path, src_md.permissions())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Ownership can only be kept when running as root or the owner; best-effort otherwise.
+        let _ = std::os::unix::fs::chown(path, Some(src_md.uid()), Some(src_md.gid()));
+    }
+    Ok(())
+}
+
+fn remove_original(src: &Path) -> anyhow::Result<()> {
+    std::fs::remove_file(src).with_context(|| format!("Failed to remove {}", src.display()))
+}
+
+/// Atomically puts a symlink to `target` at `original`.
+#[cfg(unix)]
+fn link_back(target: &Path, original: &Path) -> anyhow::Result<()> {
+    let mut tmp_name = original
+        .file_name()
+        .context("Original has no file name")?
+        .to_os_string();
+    tmp_name.push(".lff-link");
+    let tmp = original.with_file_name(tmp_name);
+    let _ = std::fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(target, &tmp)
+        .with_context(|| format!("Failed to create symlink {}", tmp.display()))?;
+    std::fs::rename(&tmp, original)
+        .with_context(|| format!("Failed to replace {} with a symlink", original.display()))
+}
+
+#[cfg(not(unix))]
+fn link_back(_target: &Path, _original: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("--leave-symlink is only supported on Unix-like systems")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("lff-reloc-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("src/logs")).unwrap();
+        base.canonicalize().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relocate_mirrors_path_and_leaves_symlink() {
+        let base = scratch("move");
+        let file = base.join("src/logs/app.log");
+        std::fs::write(&file, b"hello").unwrap();
+
+        let relocator = Relocator::new(&base.join("src"), &base.join("cold"), true).unwrap();
+        let target = relocator.relocate(&file).unwrap().unwrap();
+        assert_eq!(target, base.join("cold/logs/app.log"));
+        assert_eq!(std::fs::read(&target).unwrap(), b"hello");
+        assert_eq!(std::fs::read_link(&file).unwrap(), target);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn resume_reuses_matching_copy_and_rejects_different_one() {
+        let base = scratch("resume");
+        let file = base.join("src/logs/app.log");
+        std::fs::write(&file, b"hello").unwrap();
+        std::fs::create_dir_all(base.join("cold/logs")).unwrap();
+        std::fs::write(base.join("cold/logs/app.log"), b"hellO").unwrap();
+
+        let relocator = Relocator::new(&base.join("src"), &base.join("cold"), false).unwrap();
+        assert!(relocator.relocate(&file).is_err());
+        assert!(file.exists());
+
+        std::fs::write(base.join("cold/logs/app.log"), b"hello").unwrap();
+        relocator.relocate(&file).unwrap();
+        assert!(!file.exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn copy_verified_preserves_content_and_mtime() {
+        let base = scratch("copy");
+        let file = base.join("src/logs/app.log");
+        std::fs::write(&file, vec![7u8; 3 << 20]).unwrap();
+        let old = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let target = base.join("copy.log");
+        copy_verified(&file, &target).unwrap();
+        assert!(same_content(&file, &target).unwrap());
+        assert_eq!(std::fs::metadata(&target).unwrap().modified().unwrap(), old);
+        assert!(!base.join("copy.log.lff-partial").exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn failed_copy_removes_the_partial_file() {
+        let base = scratch("copy-fail");
+        let file = base.join("src/logs/app.log");
+        std::fs::write(&file, vec![7u8; 4096]).unwrap();
+        let src_md = std::fs::metadata(&file).unwrap();
+        // Yields half of the file, then fails like a read from a dying disk.
+        let failing = (&[7u8; 2048][..]).chain(FailingReader);
+
+        let target = base.join("copy.log");
+        let err = copy_verified_from(failing, &src_md, &target).unwrap_err();
+        assert!(err.to_string().contains("disk went away"));
+        assert!(!target.exists());
+        assert!(!base.join("copy.log.lff-partial").exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk went away"))
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserve_metadata_copies_read_only_mode_and_mtime() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = scratch("readonly");
+        let (file, copy) = (base.join("src/ro.bin"), base.join("src/ro.bin.lff-partial"));
+        std::fs::write(&file, b"frozen").unwrap();
+        std::fs::write(&copy, b"frozen").unwrap();
+        let old = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o444)).unwrap();
+
+        preserve_metadata(&std::fs::metadata(&file).unwrap(), &copy).unwrap();
+        let md = std::fs::metadata(&copy).unwrap();
+        assert_eq!(md.permissions().mode() & 0o777, 0o444);
+        assert_eq!(md.modified().unwrap(), old);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+use crate::tree::DirTree;
+use anyhow::Context;
+use humansize::{format_size, BINARY};
+use largest_file_finder::{IndexEntry, SizedPath};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Logical treemap canvas; rectangles are emitted as percentages of it so the page can scale.
+const CANVAS_W: f64 = 1200.0;
+const CANVAS_H: f64 = 720.0;
+/// Nesting depth below the starting directory drawn in the treemap.
+const MAX_DEPTH: usize = 6;
+/// Rectangles smaller than this (in canvas px²) are not drawn, which bounds the page size.
+const MIN_AREA: f64 = 24.0;
+const MAX_EXTENSIONS: usize = 50;
+
+/// Everything that goes into one HTML report.
+pub struct Report<'a> {
+    pub tree: &'a DirTree,
+    pub top: &'a [SizedPath],
+    pub records: &'a [IndexEntry],
+    /// Label/value pairs describing how the data was gathered.
+    pub provenance: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExtStat {
+    ext: String,
+    files: u64,
+    bytes: u64,
+}
+
+/// Writes a single self-contained HTML file (inline CSS, no scripts or external assets).
+pub fn write_html(out: &Path, report: &Report) -> anyhow::Result<()> {
+    let file = File::create(out)
+        .with_context(|| format!("Failed to create report file: {}", out.display()))?;
+    let mut w = BufWriter::new(file);
+    w.write_all(render(report).as_bytes())
+        .context("Failed to write HTML report")?;
+    w.flush().context("Failed to flush HTML report")?;
+    Ok(())
+}
+
+fn render(report: &Report) -> String {
+    let mut html = String::new();
+    html.push_str(concat!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>Disk usage report</title>\n<style>\n",
+        "body{font:14px system-ui,sans-serif;margin:24px;color:#222}\n",
+        "h1{font-size:22px}h2{font-size:18px;margin-top:32px}\n",
+        "table{border-collapse:collapse}td,th{padding:3px 10px;text-align:left;border-bottom:1px solid #ddd}\n",
+        "td.n,th.n{text-align:right;font-variant-numeric:tabular-nums}\n",
+        ".tm{position:relative;width:100%;max-width:1200px;aspect-ratio:1200/720;background:#eee}\n",
+        ".tm div{position:absolute;box-sizing:border-box;border:1px solid rgba(0,0,0,.25);overflow:hidden;",
+        "font-size:11px;line-height:13px;padding:0 2px;white-space:nowrap;text-overflow:ellipsis}\n",
+        "</style>\n</head>\n<body>\n<h1>Disk usage report</h1>\n",
+    ));
+
+    html.push_str("<h2>Provenance</h2>\n<table>\n");
+    for (label, value) in &report.provenance {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(label),
+            escape(value)
+        );
+    }
+    html.push_str("</table>\n");
+
+    let start = report.tree.start_node();
+    let _ = writeln!(
+        html,
+        "<h2>Treemap of {} ({})</h2>\n<div class=\"tm\">",
+        escape(&report.tree.path_of(start).to_string_lossy()),
+        format_size(report.tree.node(start).size, BINARY)
+    );
+    let canvas = Rect {
+        x: 0.0,
+        y: 0.0,
+        w: CANVAS_W,
+        h: CANVAS_H,
+    };
+    render_treemap(&mut html, report.tree, start, canvas, 0);
+    html.push_str("</div>\n");
+
+    html.push_str("<h2>Top files</h2>\n<table>\n<tr><th class=\"n\">#</th><th class=\"n\">Size</th><th>Path</th></tr>\n");
+    for (idx, item) in report.top.iter().enumerate() {
+        let _ = writeln!(
+            html,
+            "<tr><td class=\"n\">{}</td><td class=\"n\">{}</td><td>{}</td></tr>",
+            idx + 1,
+            format_size(item.size, BINARY),
+            escape(&item.path.to_string_lossy())
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Extensions</h2>\n<table>\n<tr><th>Extension</th><th class=\"n\">Files</th><th class=\"n\">Size</th></tr>\n");
+    for stat in extension_breakdown(report.records) {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+            escape(&stat.ext),
+            stat.files,
+            format_size(stat.bytes, BINARY)
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn render_treemap(html: &mut String, tree: &DirTree, idx: usize, rect: Rect, depth: usize) {
+    let children: Vec<usize> = tree
+        .sorted_children(idx)
+        .into_iter()
+        .filter(|&c| tree.node(c).size > 0)
+        .collect();
+    let sizes: Vec<u64> = children.iter().map(|&c| tree.node(c).size).collect();
+
+    for (&child, r) in children.iter().zip(squarify(&sizes, rect)) {
+        if r.w * r.h < MIN_AREA {
+            continue;
+        }
+        let node = tree.node(child);
+        let label = if r.w > 40.0 && r.h > 13.0 {
+            escape(&node.name)
+        } else {
+            String::new()
+        };
+        let _ = writeln!(
+            html,
+            "<div style=\"left:{:.3}%;top:{:.3}%;width:{:.3}%;height:{:.3}%;background:hsl({},{}%,{}%)\" title=\"{} ({})\">{label}</div>",
+            r.x / CANVAS_W * 100.0,
+            r.y / CANVAS_H * 100.0,
+            r.w / CANVAS_W * 100.0,
+            r.h / CANVAS_H * 100.0,
+            hue(&node.name),
+            if node.is_dir { 35 } else { 55 },
+            (92 - 8 * depth as i64).max(50),
+            escape(&tree.path_of(child).to_string_lossy()),
+            format_size(node.size, BINARY)
+        );
+
+        // Leave a strip at the top for the directory label, then nest its children.
+        if node.is_dir && depth + 1 < MAX_DEPTH {
+            let header = if r.h > 30.0 { 13.0 } else { 0.0 };
+            let inner = Rect {
+                x: r.x + 2.0,
+                y: r.y + header + 1.0,
+                w: r.w - 4.0,
+                h: r.h - header - 3.0,
+            };
+            if inner.w > 2.0 && inner.h > 2.0 {
+                render_treemap(html, tree, child, inner, depth + 1);
+            }
+        }
+    }
+}
+
+/// Squarified treemap layout (Bruls, Huizing & van Wijk): `sizes` must be sorted largest first.
+fn squarify(sizes: &[u64], rect: Rect) -> Vec<Rect> {
+    let total: f64 = sizes.iter().map(|&s| s as f64).sum();
+    if total <= 0.0 || rect.w <= 0.0 || rect.h <= 0.0 {
+        return vec![
+            Rect {
+                w: 0.0,
+                h: 0.0,
+                ..rect
+            };
+            sizes.len()
+        ];
+    }
+    let scale = rect.w * rect.h / total;
+    let areas: Vec<f64> = sizes.iter().map(|&s| s as f64 * scale).collect();
+
+    let mut out = Vec::with_capacity(areas.len());
+    let mut free = rect;
+    let mut row: Vec<f64> = Vec::new();
+    for &area in &areas {
+        let side = free.w.min(free.h);
+        let mut candidate = row.clone();
+        candidate.push(area);
+        if row.is_empty() || worst(&candidate, side) <= worst(&row, side) {
+            row = candidate;
+        } else {
+            layout_row(&row, &mut free, &mut out);
+            row = vec![area];
+        }
+    }
+    if !row.is_empty() {
+        layout_row(&row, &mut free, &mut out);
+    }
+    out
+}
+
+/// Worst aspect ratio of a row laid along a side of length `side`.
+fn worst(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(f64::MIN, f64::max);
+    let min = row.iter().cloned().fold(f64::MAX, f64::min);
+    let side2 = side * side;
+    let sum2 = sum * sum;
+    (side2 * max / sum2).max(sum2 / (side2 * min))
+}
+
+fn layout_row(row: &[f64], free: &mut Rect, out: &mut Vec<Rect>) {
+    let sum: f64 = row.iter().sum();
+    if free.w >= free.h {
+        // Column along the left edge.
+        let width = sum / free.h;
+        let mut y = free.y;
+        for &area in row {
+            let h = area / width;
+            out.push(Rect {
+                x: free.x,
+                y,
+                w: width,
+                h,
+            });
+            y += h;
+        }
+        free.x += width;
+        free.w -= width;
+    } else {
+        // Row along the top edge.
+        let height = sum / free.w;
+        let mut x = free.x;
+        for &area in row {
+            let w = area / height;
+            out.push(Rect {
+                x,
+                y: free.y,
+                w,
+                h: height,
+            });
+            x += w;
+        }
+        free.y += height;
+        free.h -= height;
+    }
+}
+
+/// Per-extension totals, largest first; the long tail is folded into "(other)".
+fn extension_breakdown(records: &[IndexEntry]) -> Vec<ExtStat> {
+    let mut by_ext: HashMap<String, (u64, u64)> = HashMap::new();
+    for rec in records {
+        let ext = Path::new(&rec.path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_else(|| "(none)".to_string());
+        let entry = by_ext.entry(ext).or_default();
+        entry.0 += 1;
+        entry.1 += rec.size;
+    }
+
+    let mut stats: Vec<ExtStat> = by_ext
+        .into_iter()
+        .map(|(ext, (files, bytes))| ExtStat { ext, files, bytes })
+        .collect();
+    stats.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.ext.cmp(&b.ext)));
+
+    if stats.len() > MAX_EXTENSIONS {
+        let tail = stats.split_off(MAX_EXTENSIONS - 1);
+        stats.push(ExtStat {
+            ext: "(other)".to_string(),
+            files: tail.iter().map(|s| s.files).sum(),
+            bytes: tail.iter().map(|s| s.bytes).sum(),
+        });
+    }
+    stats
+}
+
+fn hue(name: &str) -> u32 {
+    name.bytes()
+        .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(u32::from(b)))
+        % 360
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squarify_fills_rect_proportionally() {
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: 600.0,
+            h: 400.0,
+        };
+        let sizes = [6, 6, 4, 3, 2, 2, 1];
+        let rects = squarify(&sizes, rect);
+        assert_eq!(rects.len(), sizes.len());
+
+        let total: u64 = sizes.iter().sum();
+        for (size, r) in sizes.iter().zip(&rects) {
+            let expected = *size as f64 / total as f64 * 600.0 * 400.0;
+            assert!((r.w * r.h - expected).abs() < 1e-6);
+            assert!(r.x >= 0.0 && r.y >= 0.0);
+            assert!(r.x + r.w <= 600.0 + 1e-6 && r.y + r.h <= 400.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn extension_breakdown_groups_case_insensitively() {
+        let rec = IndexEntry::new;
+        let stats =
+            extension_breakdown(&[rec("/a/x.MP4", 10), rec("/a/y.mp4", 5), rec("/a/README", 1)]);
+        assert_eq!(
+            stats,
+            vec![
+                ExtStat {
+                    ext: "mp4".to_string(),
+                    files: 2,
+                    bytes: 15
+                },
+                ExtStat {
+                    ext: "(none)".to_string(),
+                    files: 1,
+                    bytes: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn escape_neutralizes_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">&'"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;"
+        );
+    }
+}
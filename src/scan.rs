@@ -0,0 +1,388 @@
+//! Walking a directory tree and collecting the files that match.
+
+use crate::collect::Collector;
+use crate::index::IndexEntry;
+use crate::mounts::MountFilter;
+use crate::scan_errors::ScanErrors;
+use crate::source::{EntrySource, Pipeline, Record};
+use crate::vfs::{FileId, FileKind, Filesystem, Metadata, RealFs};
+use crate::{archive, ignore_files, Error, Matcher, Result};
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Walks a directory tree, handing every matching file to [`Collector`]s. The tree is read through
+/// a [`Filesystem`], the real one unless given with [`Scanner::with_filesystem`].
+///
+/// ```no_run
+/// # use largest_file_finder::{Ranking, Scanner, TopN};
+/// # fn main() -> largest_file_finder::Result<()> {
+/// let mut top = TopN::new(5, Ranking::by_size());
+/// let outcome = Scanner::new("/home").min_bytes(1 << 20).scan(&mut [&mut top])?;
+/// eprintln!("{}", outcome.errors.summary(outcome.files, outcome.bytes));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Scanner<F = RealFs> {
+    fs: F,
+    root: PathBuf,
+    follow_symlinks: bool,
+    one_file_system: bool,
+    inspect_archives: bool,
+    ignore_files: bool,
+    mount_filter: MountFilter,
+    errors_log: Option<PathBuf>,
+    verbose: bool,
+    matcher: Matcher,
+    min_bytes: u64,
+}
+
+/// What a scan saw.
+#[derive(Debug)]
+pub struct ScanOutcome {
+    /// Regular files walked, matching or not
+    pub files: u64,
+    pub bytes: u64,
+    /// Directories skipped without walking them, by exclude patterns, ignore files or mount filter
+    pub pruned: u64,
+    pub errors: ScanErrors,
+}
+
+impl Scanner {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::with_filesystem(RealFs, root)
+    }
+}
+
+impl<F: Filesystem> Scanner<F> {
+    pub fn with_filesystem(fs: F, root: impl Into<PathBuf>) -> Self {
+        Self {
+            fs,
+            root: root.into(),
+            follow_symlinks: false,
+            one_file_system: false,
+            inspect_archives: false,
+            ignore_files: false,
+            mount_filter: MountFilter::default(),
+            errors_log: None,
+            verbose: false,
+            matcher: Matcher::new(),
+            min_bytes: 0,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn follow_symlinks(mut self, yes: bool) -> Self {
+        self.follow_symlinks = yes;
+        self
+    }
+
+    /// Stays on the filesystem of the root.
+    pub fn one_file_system(mut self, yes: bool) -> Self {
+        self.one_file_system = yes;
+        self
+    }
+
+    /// Also lists the members of tar, tar.gz and zip archives, as `ARCHIVE!/MEMBER` paths.
+    pub fn inspect_archives(mut self, yes: bool) -> Self {
+        self.inspect_archives = yes;
+        self
+    }
+
+    /// Honors `.gitignore`, `.ignore` and `.lffignore` files.
+    pub fn ignore_files(mut self, yes: bool) -> Self {
+        self.ignore_files = yes;
+        self
+    }
+
+    /// Mount points not to enter; by default every mount is walked.
+    pub fn mount_filter(mut self, filter: MountFilter) -> Self {
+        self.mount_filter = filter;
+        self
+    }
+
+    /// Logs every error met as a line of JSON to `path`.
+    pub fn errors_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.errors_log = Some(path.into());
+        self
+    }
+
+    /// Reports progress on stderr.
+    pub fn verbose(mut self, yes: bool) -> Self {
+        self.verbose = yes;
+        self
+    }
+
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Ignores files smaller than this.
+    pub fn min_bytes(mut self, bytes: u64) -> Self {
+        self.min_bytes = bytes;
+        self
+    }
+
+    /// Walks the tree, handing each matching file to every collector in `collectors`, and every
+    /// file to those that [want them all](Collector::wants_unmatched).
+    pub fn scan(&self, collectors: &mut [&mut dyn Collector]) -> Result<ScanOutcome> {
+        if self.fs.metadata(&self.root).is_err() {
+            return Err(Error::RootNotFound(self.root.clone()));
+        }
+        let mut errors = ScanErrors::new(&self.root, self.errors_log.as_deref())?;
+        let (files, bytes, pruned) = {
+            let mut walk = Walk::new(self, &mut errors);
+            Pipeline::new(&self.matcher, self.min_bytes)
+                .verbose(self.verbose)
+                .run(&mut walk, collectors)?;
+            (walk.files, walk.bytes, walk.pruned)
+        };
+        errors.flush()?;
+        Ok(ScanOutcome {
+            files,
+            bytes,
+            pruned,
+            errors,
+        })
+    }
+}
+
+/// The depth-first directory walk of a [`Scanner`], as an [`EntrySource`]. A directory is admitted
+/// before its contents, and archive members, with `inspect_archives`, follow the archive itself.
+struct Walk<'a, F> {
+    scanner: &'a Scanner<F>,
+    errors: &'a mut ScanErrors,
+    /// Mount points are absolute; the walk yields paths below `root` as given.
+    abs_root: PathBuf,
+    root_dev: Option<u64>,
+    ignores: Option<ignore_files::IgnoreStack>,
+    /// The root, until it has been visited
+    start: Option<PathBuf>,
+    /// Directories being listed, innermost last
+    stack: Vec<Frame<'a>>,
+    /// Members of the last archive, not yet handed out
+    pending: VecDeque<Record>,
+    files: u64,
+    bytes: u64,
+    /// Directories skipped without walking them, by exclude patterns, ignore files or mount filter
+    pruned: u64,
+}
+
+/// How many directories may be listed through a live handle at once. Outer directories are read
+/// into memory past this depth, so deep trees do not run out of file descriptors.
+const MAX_OPEN: usize = 10;
+
+struct Frame<'a> {
+    dir: PathBuf,
+    id: Option<FileId>,
+    children: Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>,
+    /// Whether `children` has been read into memory, closing the directory handle
+    buffered: bool,
+}
+
+impl Frame<'_> {
+    fn buffer(&mut self) {
+        if !self.buffered {
+            let rest: Vec<_> = self.children.by_ref().collect();
+            self.children = Box::new(rest.into_iter());
+            self.buffered = true;
+        }
+    }
+}
+
+impl<'a, F: Filesystem> Walk<'a, F> {
+    fn new(scanner: &'a Scanner<F>, errors: &'a mut ScanErrors) -> Self {
+        let root = &scanner.root;
+        Self {
+            scanner,
+            errors,
+            abs_root: scanner
+                .fs
+                .canonicalize(root)
+                .unwrap_or_else(|_| root.to_path_buf()),
+            root_dev: scanner
+                .fs
+                .metadata(root)
+                .ok()
+                .and_then(|md| md.id)
+                .map(|id| id.dev),
+            ignores: scanner.ignore_files.then(ignore_files::IgnoreStack::new),
+            start: Some(root.to_path_buf()),
+            stack: Vec::new(),
+            pending: VecDeque::new(),
+            files: 0,
+            bytes: 0,
+            pruned: 0,
+        }
+    }
+
+    /// The next path to look at and its depth below the root.
+    fn next_path(&mut self) -> Result<Option<(PathBuf, usize)>> {
+        if let Some(root) = self.start.take() {
+            return Ok(Some((root, 0)));
+        }
+        while let Some(frame) = self.stack.last_mut() {
+            match frame.children.next() {
+                Some(Ok(path)) => return Ok(Some((path, self.stack.len()))),
+                Some(Err(err)) => {
+                    let dir = frame.dir.clone();
+                    self.errors.record_io(&dir, &err)?;
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn admit(&mut self, path: &Path, depth: usize, is_dir: bool) -> bool {
+        let Scanner {
+            fs,
+            root,
+            matcher,
+            mount_filter,
+            ..
+        } = self.scanner;
+        let excluded = is_dir
+            && if depth == 0 {
+                mount_filter.skips_root(&self.abs_root)
+            } else {
+                matcher.excludes_dir(&path.to_string_lossy())
+                    || path
+                        .strip_prefix(root)
+                        .is_ok_and(|rel| mount_filter.skips(&self.abs_root.join(rel)))
+            };
+        !excluded
+            && self
+                .ignores
+                .as_mut()
+                .is_none_or(|stack| stack.admit(fs, path, is_dir))
+    }
+
+    /// Starts listing `dir`, unless it is on another filesystem with `one_file_system` or is
+    /// reached again through a symlink.
+    fn descend(&mut self, dir: PathBuf, depth: usize, md: &Metadata) -> Result<()> {
+        let scanner = self.scanner;
+        if scanner.one_file_system && depth > 0 && md.id.map(|id| id.dev) != self.root_dev {
+            return Ok(());
+        }
+        if scanner.follow_symlinks && md.id.is_some() {
+            if let Some(ancestor) = self.stack.iter().find(|frame| frame.id == md.id) {
+                let ancestor = ancestor.dir.clone();
+                return self.errors.record_loop(&dir, &ancestor);
+            }
+        }
+        if let Some(outer) = self.stack.len().checked_sub(MAX_OPEN) {
+            self.stack[outer].buffer();
+        }
+        match scanner.fs.read_dir(&dir) {
+            Ok(children) => self.stack.push(Frame {
+                dir,
+                id: md.id,
+                children,
+                buffered: false,
+            }),
+            Err(err) => self.errors.record_io(&dir, &err)?,
+        }
+        Ok(())
+    }
+
+    /// Queues the members of the archive at `path`.
+    fn list_archive(&mut self, path: &Path) -> Result<()> {
+        let pending = &mut self.pending;
+        let listed = self
+            .scanner
+            .fs
+            .open(path)
+            .map_err(|e| Error::io(format!("Failed to open {}", path.display()), e))
+            .and_then(|file| {
+                archive::members(path, file, |m| {
+                    pending.push_back(Record {
+                        path: PathBuf::from(&m.path),
+                        entry: IndexEntry {
+                            path: m.path,
+                            size: m.size,
+                            mtime: m.mtime,
+                            atime: None,
+                            packed: m.packed,
+                        },
+                    });
+                })
+            });
+        if let Err(err) = listed {
+            self.errors.record_other(path, &err)?;
+        }
+        Ok(())
+    }
+}
+
+impl<F: Filesystem> EntrySource for Walk<'_, F> {
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        if let Some(member) = self.pending.pop_front() {
+            return Ok(Some(member));
+        }
+        while let Some((path, depth)) = self.next_path()? {
+            let fs = &self.scanner.fs;
+            // The root is always followed, like `find -H`.
+            let md = if depth == 0 || self.scanner.follow_symlinks {
+                fs.metadata(&path)
+            } else {
+                fs.symlink_metadata(&path)
+            };
+            let md = match md {
+                Ok(md) => md,
+                Err(err) => {
+                    self.errors.record_io(&path, &err)?;
+                    continue;
+                }
+            };
+
+            let is_dir = md.kind == FileKind::Dir;
+            if !self.admit(&path, depth, is_dir) {
+                if is_dir {
+                    self.pruned += 1;
+                }
+                continue;
+            }
+            if is_dir {
+                self.descend(path, depth, &md)?;
+                continue;
+            }
+            if md.kind != FileKind::File {
+                continue;
+            }
+
+            self.files += 1;
+            self.bytes += md.len;
+            if self.scanner.inspect_archives && archive::is_archive(&path) {
+                self.list_archive(&path)?;
+            }
+            return Ok(Some(Record {
+                entry: IndexEntry {
+                    path: path.to_string_lossy().into_owned(),
+                    size: md.len,
+                    mtime: md.mtime,
+                    atime: md.atime,
+                    packed: None,
+                },
+                path,
+            }));
+        }
+        Ok(None)
+    }
+
+    fn progress(&self) -> String {
+        format!(
+            "Visited: {}, errors: {}, pruned: {}",
+            self.files,
+            self.errors.total(),
+            self.pruned
+        )
+    }
+}
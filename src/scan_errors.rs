@@ -0,0 +1,215 @@
+//! Classification, counting and logging of the errors met while walking, so an incomplete scan can
+//! be told apart from a complete one.
+
+use crate::{Error, Result};
+use humansize::{format_size, BINARY};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorClass {
+    PermissionDenied,
+    /// The entry disappeared between being listed and being read
+    Vanished,
+    /// A symlink loop, with --follow-symlinks
+    Loop,
+    PathTooLong,
+    Io,
+}
+
+impl ErrorClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorClass::PermissionDenied => "permission-denied",
+            ErrorClass::Vanished => "vanished",
+            ErrorClass::Loop => "loop",
+            ErrorClass::PathTooLong => "path-too-long",
+            ErrorClass::Io => "io",
+        }
+    }
+
+    pub fn of_io(err: &std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => ErrorClass::PermissionDenied,
+            std::io::ErrorKind::NotFound => ErrorClass::Vanished,
+            std::io::ErrorKind::InvalidFilename => ErrorClass::PathTooLong,
+            _ => ErrorClass::Io,
+        }
+    }
+}
+
+/// One line of `--errors-log`.
+#[derive(Serialize)]
+struct ErrorRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    class: ErrorClass,
+    message: &'a str,
+}
+
+#[derive(Debug)]
+pub struct ScanErrors {
+    root: PathBuf,
+    by_class: BTreeMap<ErrorClass, u64>,
+    /// Keyed by the first directory below the root (or the root itself)
+    by_top_dir: BTreeMap<PathBuf, u64>,
+    log: Option<(PathBuf, BufWriter<File>)>,
+}
+
+impl ScanErrors {
+    pub fn new(root: &Path, log_path: Option<&Path>) -> Result<Self> {
+        let log = match log_path {
+            Some(path) => {
+                let file = File::create(path).map_err(|e| {
+                    Error::io(
+                        format!("Failed to create errors log: {}", path.display()),
+                        e,
+                    )
+                })?;
+                Some((path.to_path_buf(), BufWriter::new(file)))
+            }
+            None => None,
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            by_class: BTreeMap::new(),
+            by_top_dir: BTreeMap::new(),
+            log,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.by_class.values().sum()
+    }
+
+    /// Records an error from listing or reading the metadata of `path`.
+    pub(crate) fn record_io(&mut self, path: &Path, err: &std::io::Error) -> Result<()> {
+        let message = format!("IO error for operation on {}: {err}", path.display());
+        self.record(Some(path), ErrorClass::of_io(err), &message)
+    }
+
+    /// Records a followed symlink at `path` leading back to the directory `ancestor`.
+    pub(crate) fn record_loop(&mut self, path: &Path, ancestor: &Path) -> Result<()> {
+        let message = format!(
+            "File system loop found: {} points to an ancestor {}",
+            path.display(),
+            ancestor.display()
+        );
+        self.record(Some(path), ErrorClass::Loop, &message)
+    }
+
+    /// Records an error from reading inside a file (e.g. an archive), classified by its cause.
+    pub(crate) fn record_other(&mut self, path: &Path, err: &Error) -> Result<()> {
+        let cause = std::error::Error::source(err).and_then(|e| e.downcast_ref::<std::io::Error>());
+        let class = cause.map_or(ErrorClass::Io, ErrorClass::of_io);
+        let message = match cause {
+            Some(cause) => format!("{err}: {cause}"),
+            None => err.to_string(),
+        };
+        self.record(Some(path), class, &message)
+    }
+
+    fn record(&mut self, path: Option<&Path>, class: ErrorClass, message: &str) -> Result<()> {
+        *self.by_class.entry(class).or_default() += 1;
+        let top = path
+            .and_then(|p| p.strip_prefix(&self.root).ok())
+            .and_then(|rel| rel.components().next())
+            .map_or_else(|| self.root.clone(), |first| self.root.join(first));
+        *self.by_top_dir.entry(top).or_default() += 1;
+
+        if let Some((_, w)) = self.log.as_mut() {
+            let rec = ErrorRecord {
+                path: path.map(|p| p.to_string_lossy().into_owned()),
+                class,
+                message,
+            };
+            serde_json::to_writer(w.by_ref(), &rec)
+                .map_err(|e| Error::io("Failed to write errors log", e))?;
+            w.write_all(b"\n")
+                .map_err(|e| Error::io("Failed to write errors log", e))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        if let Some((_, w)) = self.log.as_mut() {
+            w.flush()
+                .map_err(|e| Error::io("Failed to flush errors log", e))?;
+        }
+        Ok(())
+    }
+
+    /// Where each error was logged, if anywhere.
+    pub fn log_path(&self) -> Option<&Path> {
+        self.log.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    /// Whether a scan of `files` files totalling `bytes` was complete, and if not what went wrong
+    /// and where.
+    pub fn summary(&self, files: u64, bytes: u64) -> String {
+        let scanned = format!("{files} files ({})", format_size(bytes, BINARY));
+        let total = self.total();
+        if total == 0 {
+            return format!("Scan complete: {scanned}, no errors");
+        }
+
+        let classes: Vec<String> = self
+            .by_class
+            .iter()
+            .map(|(class, n)| format!("{} {n}", class.as_str()))
+            .collect();
+        let mut dirs: Vec<(&PathBuf, &u64)> = self.by_top_dir.iter().collect();
+        dirs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let dirs: Vec<String> = dirs
+            .into_iter()
+            .take(5)
+            .map(|(dir, n)| format!("{} ({n})", dir.display()))
+            .collect();
+        format!(
+            "Scan incomplete: {scanned}, {total} errors: {}; most under {}",
+            classes.join(", "),
+            dirs.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_by_class_and_top_level_directory() {
+        let root = Path::new("/data");
+        let mut errors = ScanErrors::new(root, None).unwrap();
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let gone = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let mut io = |path: &str, err: &std::io::Error| {
+            let class = ErrorClass::of_io(err);
+            errors
+                .record(Some(Path::new(path)), class, &err.to_string())
+                .unwrap();
+        };
+        io("/data/a/x/1", &denied);
+        io("/data/a/2", &denied);
+        io("/data/b", &gone);
+        errors
+            .record_other(
+                Path::new("/data/c.zip"),
+                &Error::Archive {
+                    path: PathBuf::from("/data/c.zip"),
+                    source: gone,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(errors.total(), 4);
+        assert_eq!(errors.by_class[&ErrorClass::PermissionDenied], 2);
+        assert_eq!(errors.by_class[&ErrorClass::Vanished], 2);
+        assert_eq!(errors.by_top_dir[Path::new("/data/a")], 2);
+        assert_eq!(errors.by_top_dir[Path::new("/data/b")], 1);
+    }
+}
@@ -0,0 +1,159 @@
+//! Where files come from, and the one loop that filters and collects them whatever the source.
+
+use crate::collect::Collector;
+use crate::{IndexEntry, Matcher, Result};
+use std::path::PathBuf;
+
+/// How many records pass between two `verbose` progress lines.
+const PROGRESS_EVERY: u64 = 200_000;
+
+/// A file yielded by an [`EntrySource`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The real path, which differs from `entry.path` only when it is not valid UTF-8
+    pub path: PathBuf,
+    pub entry: IndexEntry,
+}
+
+impl From<IndexEntry> for Record {
+    fn from(entry: IndexEntry) -> Self {
+        Self {
+            path: PathBuf::from(&entry.path),
+            entry,
+        }
+    }
+}
+
+/// Yields the files a [`Pipeline`] filters and collects: a directory walk, an index, or anything
+/// else that can list paths with their sizes.
+pub trait EntrySource {
+    /// The next file, or `None` once the source is exhausted. An error ends the pipeline; errors a
+    /// source can carry on after should be recorded by the source and skipped.
+    fn next_record(&mut self) -> Result<Option<Record>>;
+
+    /// Counters for the progress lines printed with `verbose`, e.g. `Visited: 1200, errors: 3`.
+    fn progress(&self) -> String;
+}
+
+/// An [`EntrySource`] over an iterator of entries, such as a file list exported by a storage
+/// system and parsed by the caller.
+///
+/// ```
+/// # use largest_file_finder::{Entries, IndexEntry, Matcher, Pipeline, Ranking, TopN};
+/// let mut source = Entries::new([IndexEntry::new("/a", 3), IndexEntry::new("/b", 9)]);
+/// let mut top = TopN::new(1, Ranking::by_size());
+/// Pipeline::new(&Matcher::new(), 0).run(&mut source, &mut [&mut top])?;
+/// assert_eq!(top.into_sorted_vec()[0].size, 9);
+/// # Ok::<(), largest_file_finder::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Entries<I> {
+    entries: I,
+    yielded: u64,
+}
+
+impl<I: Iterator<Item = IndexEntry>> Entries<I> {
+    pub fn new(entries: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            entries: entries.into_iter(),
+            yielded: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = IndexEntry>> EntrySource for Entries<I> {
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        let next = self.entries.next().map(Record::from);
+        self.yielded += u64::from(next.is_some());
+        Ok(next)
+    }
+
+    fn progress(&self) -> String {
+        format!("Entries: {}", self.yielded)
+    }
+}
+
+/// Applies a [`Matcher`] and a minimum size to the records of an [`EntrySource`] and hands the
+/// files that pass to [`Collector`]s.
+#[derive(Debug, Clone, Copy)]
+pub struct Pipeline<'a> {
+    matcher: &'a Matcher,
+    min_bytes: u64,
+    verbose: bool,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(matcher: &'a Matcher, min_bytes: u64) -> Self {
+        Self {
+            matcher,
+            min_bytes,
+            verbose: false,
+        }
+    }
+
+    /// Reports progress on stderr.
+    pub fn verbose(mut self, yes: bool) -> Self {
+        self.verbose = yes;
+        self
+    }
+
+    /// Drains `source`, handing each record that passes the filters to every collector, and the
+    /// others to the collectors that [want them](Collector::wants_unmatched). Returns the number
+    /// of records read.
+    pub fn run<S: EntrySource + ?Sized>(
+        &self,
+        source: &mut S,
+        collectors: &mut [&mut dyn Collector],
+    ) -> Result<u64> {
+        let mut read: u64 = 0;
+        let mut matched: u64 = 0;
+        while let Some(record) = source.next_record()? {
+            read += 1;
+            let entry = &record.entry;
+            let passes =
+                entry.size >= self.min_bytes && self.matcher.matches(&entry.path, entry.size);
+            matched += u64::from(passes);
+            for collector in collectors.iter_mut() {
+                if passes || collector.wants_unmatched() {
+                    collector.collect(&record)?;
+                }
+            }
+
+            if self.verbose && read.is_multiple_of(PROGRESS_EVERY) {
+                let mut line = format!("{}, matched: {matched}", source.progress());
+                for progress in collectors.iter().filter_map(|c| c.progress()) {
+                    line.push_str(", ");
+                    line.push_str(&progress);
+                }
+                eprintln!("{line}");
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Expr, Ranking, TopN};
+
+    #[test]
+    fn pipeline_collects_only_matches_above_the_minimum() {
+        let mut source = Entries::new([
+            IndexEntry::new("/big.iso", 50),
+            IndexEntry::new("/small.iso", 5),
+            IndexEntry::new("/big.txt", 90),
+        ]);
+        let matcher = Matcher::new().with_query(Expr::parse("ext:iso").unwrap());
+        let mut top = TopN::new(10, Ranking::by_size());
+        let mut matched: Vec<IndexEntry> = Vec::new();
+
+        let read = Pipeline::new(&matcher, 10)
+            .run(&mut source, &mut [&mut top, &mut matched])
+            .unwrap();
+
+        assert_eq!(read, 3);
+        assert_eq!(matched, [IndexEntry::new("/big.iso", 50)]);
+        assert_eq!(top.len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+//! Summary statistics over the matched files: totals, a size histogram and the extensions taking
+//! up the most space.
+
+use humansize::{format_size, BINARY};
+use largest_file_finder::{Collector, GroupBy, Histogram, Record};
+
+/// Lower bounds of the size histogram buckets.
+const BUCKETS: [u64; 7] = [0, 4 << 10, 1 << 20, 16 << 20, 256 << 20, 1 << 30, 16 << 30];
+
+#[derive(Debug)]
+pub struct Stats {
+    files: u64,
+    bytes: u64,
+    largest: Option<(String, u64)>,
+    by_ext: GroupBy,
+    by_size: Histogram,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            files: 0,
+            bytes: 0,
+            largest: None,
+            by_ext: GroupBy::extension(),
+            by_size: Histogram::new(BUCKETS.to_vec()),
+        }
+    }
+}
+
+impl Collector for Stats {
+    fn collect(&mut self, record: &Record) -> largest_file_finder::Result<()> {
+        let size = record.entry.size;
+        self.files += 1;
+        self.bytes += size;
+        if self.largest.as_ref().is_none_or(|(_, s)| size > *s) {
+            self.largest = Some((record.entry.path.clone(), size));
+        }
+        self.by_ext.collect(record)?;
+        self.by_size.collect(record)
+    }
+}
+
+impl Stats {
+    pub fn print(&self, top: usize) {
+        println!(
+            "Files: {}, total {}",
+            self.files,
+            format_size(self.bytes, BINARY)
+        );
+        if self.files == 0 {
+            return;
+        }
+        if let Some((path, size)) = &self.largest {
+            println!(
+                "Largest: {} ({}), mean {}",
+                path,
+                format_size(*size, BINARY),
+                format_size(self.bytes / self.files, BINARY)
+            );
+        }
+
+        let share = |bytes: u64| bytes as f64 * 100.0 / self.bytes.max(1) as f64;
+        println!();
+        println!("By size:");
+        for (low, high, totals) in self.by_size.buckets() {
+            if totals.files == 0 {
+                continue;
+            }
+            let range = match high {
+                Some(high) => format!(
+                    "{} - {}",
+                    format_size(low, BINARY),
+                    format_size(high, BINARY)
+                ),
+                None => format!(">= {}", format_size(low, BINARY)),
+            };
+            println!(
+                "{range}\t{} files\t{}\t{:.1}%",
+                totals.files,
+                format_size(totals.bytes, BINARY),
+                share(totals.bytes)
+            );
+        }
+
+        println!();
+        println!("By extension:");
+        for (ext, totals) in self.by_ext.largest(top) {
+            let ext = if ext.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{ext}")
+            };
+            println!(
+                "{ext}\t{} files\t{}\t{:.1}%",
+                totals.files,
+                format_size(totals.bytes, BINARY),
+                share(totals.bytes)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use largest_file_finder::{IndexEntry, Totals};
+
+    #[test]
+    fn buckets_and_extensions() {
+        let mut stats = Stats::default();
+        for (path, size) in [
+            ("/a/movie.MKV", 2 << 30),
+            ("/a/b.mkv", 3 << 20),
+            ("/a/notes", 100),
+            ("/a/c.txt", 4 << 10),
+        ] {
+            stats
+                .collect(&Record::from(IndexEntry::new(path, size)))
+                .unwrap();
+        }
+
+        let totals = |files, bytes| Totals { files, bytes };
+        let by_size: Vec<Totals> = stats.by_size.buckets().map(|(_, _, t)| t).collect();
+        assert_eq!(stats.files, 4);
+        assert_eq!(stats.largest.as_ref().unwrap().0, "/a/movie.MKV");
+        assert_eq!(by_size[0], totals(1, 100));
+        assert_eq!(by_size[1], totals(1, 4 << 10));
+        assert_eq!(by_size[2], totals(1, 3 << 20));
+        assert_eq!(by_size[5], totals(1, 2 << 30));
+        assert_eq!(
+            stats.by_ext.largest(2),
+            vec![
+                ("mkv", totals(2, (2 << 30) + (3 << 20))),
+                ("txt", totals(1, 4 << 10))
+            ]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Move-to-trash following the freedesktop.org Trash specification 1.0.
+//!
+//! Files on the same device as `$XDG_DATA_HOME/Trash` go there; files on other volumes go to
+//! `$topdir/.Trash/$uid` (when an administrator created a sticky `.Trash`) or `$topdir/.Trash-$uid`,
+//! so trashing is always a cheap rename and never a cross-device copy.
+
+use anyhow::Context;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Moves `path` into the appropriate trash directory and returns its new location.
+#[cfg(unix)]
+pub fn trash_file(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    trash_file_with_home(path, home_trash())
+}
+
+/// [`trash_file`] with the home trash at `home`, when there is one.
+#[cfg(unix)]
+fn trash_file_with_home(path: &Path, home: Option<PathBuf>) -> anyhow::Result<Option<PathBuf>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let path = std::path::absolute(path)?;
+    let dev = std::fs::symlink_metadata(&path)
+        .with_context(|| format!("Failed to stat {}", path.display()))?
+        .dev();
+
+    let trash = match home {
+        Some(home) if device_of_existing_ancestor(&home) == Some(dev) => home,
+        _ => topdir_trash(&path, dev)?,
+    };
+    let files_dir = trash.join("files");
+    let info_dir = trash.join("info");
+    create_private_dir_all(&files_dir)?;
+    create_private_dir_all(&info_dir)?;
+
+    let file_name = path
+        .file_name()
+        .context("Cannot trash a path without a file name")?
+        .to_string_lossy()
+        .into_owned();
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&path),
+        local_timestamp()
+    );
+
+    // The .trashinfo file is created exclusively first; that reserves the name (spec §"Contents").
+    for n in 1u32.. {
+        let name = if n == 1 {
+            file_name.clone()
+        } else {
+            format!("{file_name}.{n}")
+        };
+        let info_path = info_dir.join(format!("{name}.trashinfo"));
+        let mut info_file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create {}", info_path.display()));
+            }
+        };
+        let target = files_dir.join(&name);
+        let moved = info_file
+            .write_all(info.as_bytes())
+            .map_err(anyhow::Error::from)
+            .and_then(|()| std::fs::rename(&path, &target).map_err(anyhow::Error::from));
+        if let Err(err) = moved {
+            let _ = std::fs::remove_file(&info_path);
+            return Err(err).with_context(|| format!("Failed to move {} to trash", path.display()));
+        }
+        return Ok(Some(target));
+    }
+    unreachable!("ran out of trash names")
+}
+
+#[cfg(not(unix))]
+pub fn trash_file(_path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    anyhow::bail!("--trash is only supported on Unix-like systems")
+}
+
+/// Moves a trashed file back to `original` and removes its `.trashinfo`.
+pub fn restore_file(trashed: &Path, original: &Path) -> anyhow::Result<Option<PathBuf>> {
+    if original.symlink_metadata().is_ok() {
+        anyhow::bail!("{} already exists", original.display());
+    }
+    if let Some(parent) = original.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to recreate {}", parent.display()))?;
+    }
+    std::fs::rename(trashed, original)
+        .with_context(|| format!("Failed to move {} back", trashed.display()))?;
+
+    if let (Some(files_dir), Some(name)) = (trashed.parent(), trashed.file_name()) {
+        if let Some(trash) = files_dir.parent() {
+            let mut info_name = name.to_os_string();
+            info_name.push(".trashinfo");
+            let _ = std::fs::remove_file(trash.join("info").join(info_name));
+        }
+    }
+    Ok(Some(original.to_path_buf()))
+}
+
+fn home_trash() -> Option<PathBuf> {
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))?;
+    Some(data.join("Trash"))
+}
+
+#[cfg(unix)]
+fn device_of_existing_ancestor(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    path.ancestors()
+        .find_map(|p| std::fs::metadata(p).ok())
+        .map(|md| md.dev())
+}
+
+/// Trash directory at the top of the mount containing `path`.
+#[cfg(unix)]
+fn topdir_trash(path: &Path, dev: u64) -> anyhow::Result<PathBuf> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let mut topdir = path.parent().context("File has no parent directory")?;
+    while let Some(parent) = topdir.parent() {
+        match std::fs::metadata(parent) {
+            Ok(md) if md.dev() == dev => topdir = parent,
+            _ => break,
+        }
+    }
+
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    let shared = topdir.join(".Trash");
+    if let Ok(md) = std::fs::symlink_metadata(&shared) {
+        let sticky = md.permissions().mode() & 0o1000 != 0;
+        if md.is_dir() && sticky {
+            return Ok(shared.join(uid.to_string()));
+        }
+    }
+    Ok(topdir.join(format!(".Trash-{uid}")))
+}
+
+#[cfg(unix)]
+fn create_private_dir_all(dir: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create trash directory: {}", dir.display()))
+}
+
+/// `DeletionDate` is local time without a zone, `YYYY-MM-DDThh:mm:ss`.
+#[cfg(unix)]
+fn local_timestamp() -> String {
+    // SAFETY: localtime_r only writes to the provided `tm`; a null-free time_t is always valid.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+}
+
+/// Percent-encodes a path as the spec requires for the `Path` key (RFC 2396 escaping, `/` kept).
+fn percent_encode(path: &Path) -> String {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"/-_.!~*'()".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode_escapes_reserved_and_non_ascii_bytes() {
+        assert_eq!(
+            percent_encode(Path::new("/tmp/a b/ä%.txt")),
+            "/tmp/a%20b/%C3%A4%25.txt"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn trash_and_restore_round_trip() {
+        let base = std::env::temp_dir().join(format!("lff-trash-{}", std::process::id()));
+        let file = base.join("data/report.csv");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, b"x").unwrap();
+
+        // Point the home trash into the temp dir so the test does not touch the real one.
+        let trashed = trash_file_with_home(&file, Some(base.join("share/Trash")))
+            .unwrap()
+            .unwrap();
+        assert!(!file.exists());
+        assert!(trashed.exists());
+        let info = base.join("share/Trash/info/report.csv.trashinfo");
+        let info_text = std::fs::read_to_string(&info).unwrap();
+        assert!(info_text.starts_with("[Trash Info]\nPath=/"));
+        assert!(info_text.contains("DeletionDate="));
+
+        restore_file(&trashed, &file).unwrap();
+        assert!(file.exists());
+        assert!(!info.exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// A directory or file in a [`DirTree`]; directory sizes are aggregates of everything below.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub size: u64,
+    pub files: u64,
+    pub mtime: Option<i64>,
+    pub is_dir: bool,
+}
+
+/// In-memory directory tree built from matched files, with per-directory aggregate sizes.
+///
+/// Nodes live in an arena; index 0 is a synthetic root above the first path component.
+#[derive(Debug, Clone)]
+pub struct DirTree {
+    nodes: Vec<Node>,
+    lookup: HashMap<(usize, String), usize>,
+}
+
+impl Default for DirTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DirTree {
+    pub const ROOT: usize = 0;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node {
+                name: String::new(),
+                parent: None,
+                children: Vec::new(),
+                size: 0,
+                files: 0,
+                mtime: None,
+                is_dir: true,
+            }],
+            lookup: HashMap::new(),
+        }
+    }
+
+    pub fn node(&self, idx: usize) -> &Node {
+        &self.nodes[idx]
+    }
+
+    /// Adds a file, creating intermediate directories and updating every ancestor's totals.
+    pub fn insert(&mut self, path: &Path, size: u64, mtime: Option<i64>) {
+        let names: Vec<String> = path
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let Some((file_name, dirs)) = names.split_last() else {
+            return;
+        };
+
+        let mut cur = Self::ROOT;
+        self.add_totals(cur, size);
+        for name in dirs {
+            cur = self.child_or_insert(cur, name, true);
+            self.add_totals(cur, size);
+        }
+        let leaf = self.child_or_insert(cur, file_name, false);
+        let node = &mut self.nodes[leaf];
+        node.size += size;
+        node.files += 1;
+        node.mtime = mtime;
+    }
+
+    /// Subtracts a removed file from the tree, pruning directories that become empty.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        let Some(leaf) = self.find(path) else {
+            return false;
+        };
+        if self.nodes[leaf].is_dir {
+            return false;
+        }
+        let size = self.nodes[leaf].size;
+        let files = self.nodes[leaf].files;
+
+        let mut cur = Some(leaf);
+        while let Some(idx) = cur {
+            let node = &mut self.nodes[idx];
+            node.size = node.size.saturating_sub(size);
+            node.files = node.files.saturating_sub(files);
+            let parent = node.parent;
+            if idx != Self::ROOT && node.files == 0 {
+                if let Some(p) = parent {
+                    self.nodes[p].children.retain(|&c| c != idx);
+                    let name = self.nodes[idx].name.clone();
+                    self.lookup.remove(&(p, name));
+                }
+            }
+            cur = parent;
+        }
+        true
+    }
+
+    pub fn find(&self, path: &Path) -> Option<usize> {
+        let mut cur = Self::ROOT;
+        for c in path.components() {
+            if matches!(c, Component::CurDir) {
+                continue;
+            }
+            let name = c.as_os_str().to_string_lossy().into_owned();
+            cur = *self.lookup.get(&(cur, name))?;
+        }
+        Some(cur)
+    }
+
+    /// Reconstructs the full path of a node.
+    pub fn path_of(&self, idx: usize) -> PathBuf {
+        let mut names = Vec::new();
+        let mut cur = Some(idx);
+        while let Some(i) = cur {
+            if i != Self::ROOT {
+                names.push(self.nodes[i].name.as_str());
+            }
+            cur = self.nodes[i].parent;
+        }
+        names.iter().rev().collect()
+    }
+
+    /// Children of `idx`, largest first (ties by name).
+    pub fn sorted_children(&self, idx: usize) -> Vec<usize> {
+        let mut children = self.nodes[idx].children.clone();
+        children.sort_by(|&a, &b| {
+            let (na, nb) = (&self.nodes[a], &self.nodes[b]);
+            nb.size.cmp(&na.size).then_with(|| na.name.cmp(&nb.name))
+        });
+        children
+    }
+
+    /// Follows single-directory chains from the root (e.g. `/` -> `home` -> `me`) so browsing
+    /// starts at the first directory that actually branches.
+    pub fn start_node(&self) -> usize {
+        let mut cur = Self::ROOT;
+        loop {
+            let node = &self.nodes[cur];
+            match node.children.as_slice() {
+                [only] if self.nodes[*only].is_dir => cur = *only,
+                _ => return cur,
+            }
+        }
+    }
+
+    /// Every file node at or below `idx`.
+    pub fn files_under(&self, idx: usize) -> Vec<usize> {
+        let mut out = Vec::new();
+        let mut stack = vec![idx];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if node.is_dir {
+                stack.extend(node.children.iter().copied());
+            } else {
+                out.push(i);
+            }
+        }
+        out
+    }
+
+    fn child_or_insert(&mut self, parent: usize, name: &str, is_dir: bool) -> usize {
+        if let Some(&idx) = self.lookup.get(&(parent, name.to_string())) {
+            return idx;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            parent: Some(parent),
+            children: Vec::new(),
+            size: 0,
+            files: 0,
+            mtime: None,
+            is_dir,
+        });
+        self.nodes[parent].children.push(idx);
+        self.lookup.insert((parent, name.to_string()), idx);
+        idx
+    }
+
+    fn add_totals(&mut self, idx: usize, size: u64) {
+        let node = &mut self.nodes[idx];
+        node.size += size;
+        node.files += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> DirTree {
+        let mut tree = DirTree::new();
+        tree.insert(Path::new("/data/a/big.bin"), 100, None);
+        tree.insert(Path::new("/data/a/small.txt"), 1, None);
+        tree.insert(Path::new("/data/b/mid.bin"), 50, Some(7));
+        tree
+    }
+
+    #[test]
+    fn directories_aggregate_sizes_and_sort_largest_first() {
+        let tree = sample();
+        let start = tree.start_node();
+        assert_eq!(tree.path_of(start), PathBuf::from("/data"));
+        assert_eq!(tree.node(start).size, 151);
+        assert_eq!(tree.node(start).files, 3);
+
+        let names: Vec<&str> = tree
+            .sorted_children(start)
+            .into_iter()
+            .map(|c| tree.node(c).name.as_str())
+            .collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn remove_updates_ancestors_and_prunes_empty_dirs() {
+        let mut tree = sample();
+        assert!(tree.remove(Path::new("/data/b/mid.bin")));
+        assert!(tree.find(Path::new("/data/b")).is_none());
+
+        let data = tree.find(Path::new("/data")).unwrap();
+        assert_eq!(tree.node(data).size, 101);
+        assert_eq!(tree.node(DirTree::ROOT).files, 2);
+        assert_eq!(tree.files_under(data).len(), 2);
+    }
+}
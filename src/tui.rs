@@ -0,0 +1,463 @@
+use crate::actions::{self, ActionContext, Outcome};
+use crate::trash;
+use crate::tree::DirTree;
+use humansize::{format_size, BINARY};
+use largest_file_finder::index::format_unix_secs;
+use largest_file_finder::{Expr, IndexEntry, QueryMacros};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Runs the interactive browser over already-matched records until the user quits.
+///
+/// Deletions go through `ctx`, so they get the same root/deny-list checks and audit log as
+/// `--delete`; without one the browser is read-only.
+pub fn run(
+    records: Vec<IndexEntry>,
+    source: String,
+    ctx: Option<ActionContext>,
+    macros: QueryMacros,
+) -> anyhow::Result<()> {
+    let mut app = App::new(records, source, ctx, macros);
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+enum Mode {
+    Browse,
+    /// Editing the live filter; `previous` is restored on Esc.
+    Filter {
+        input: String,
+        previous: String,
+    },
+    Confirm(Action),
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Delete,
+    Trash,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Delete => "delete",
+            Action::Trash => "trash",
+        }
+    }
+}
+
+struct App {
+    records: Vec<IndexEntry>,
+    source: String,
+    filter: String,
+    tree: DirTree,
+    cwd: usize,
+    entries: Vec<usize>,
+    list: ListState,
+    marked: BTreeSet<PathBuf>,
+    mode: Mode,
+    status: String,
+    quit: bool,
+    /// `None` when browsing read-only
+    ctx: Option<ActionContext>,
+    /// `@name` query macros usable in the filter
+    macros: QueryMacros,
+}
+
+impl App {
+    fn new(
+        records: Vec<IndexEntry>,
+        source: String,
+        ctx: Option<ActionContext>,
+        macros: QueryMacros,
+    ) -> Self {
+        let mut app = Self {
+            records,
+            source,
+            filter: String::new(),
+            tree: DirTree::new(),
+            cwd: DirTree::ROOT,
+            entries: Vec::new(),
+            list: ListState::default(),
+            marked: BTreeSet::new(),
+            mode: Mode::Browse,
+            status: String::new(),
+            quit: false,
+            ctx,
+            macros,
+        };
+        app.rebuild(None);
+        app.cwd = app.tree.start_node();
+        app.refresh_entries();
+        app
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key.code);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the tree from all records matching `expr`, staying in the current directory
+    /// when it still exists.
+    fn rebuild(&mut self, expr: Option<&Expr>) {
+        let cwd_path = self.tree.path_of(self.cwd);
+        let mut tree = DirTree::new();
+        for rec in &self.records {
+            if expr.is_none_or(|e| e.eval(&rec.path, rec.size)) {
+                tree.insert(Path::new(&rec.path), rec.size, rec.mtime);
+            }
+        }
+        self.tree = tree;
+        self.cwd = self
+            .tree
+            .find(&cwd_path)
+            .filter(|&idx| self.tree.node(idx).is_dir)
+            .unwrap_or_else(|| self.tree.start_node());
+        self.marked.retain(|p| self.tree.find(p).is_some());
+        self.refresh_entries();
+    }
+
+    fn apply_filter(&mut self, text: &str) -> anyhow::Result<()> {
+        if text.trim().is_empty() {
+            self.rebuild(None);
+        } else {
+            let expr = Expr::parse_with_macros(text, &self.macros)?;
+            self.rebuild(Some(&expr));
+        }
+        self.filter = text.to_string();
+        Ok(())
+    }
+
+    fn refresh_entries(&mut self) {
+        self.entries = self.tree.sorted_children(self.cwd);
+        let selected = self.list.selected().unwrap_or(0);
+        self.list.select(if self.entries.is_empty() {
+            None
+        } else {
+            Some(selected.min(self.entries.len() - 1))
+        });
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.list
+            .selected()
+            .and_then(|i| self.entries.get(i).copied())
+    }
+
+    /// Marked nodes, or the selected node when nothing is marked.
+    fn targets(&self) -> Vec<usize> {
+        if self.marked.is_empty() {
+            return self.selected().into_iter().collect();
+        }
+        self.marked
+            .iter()
+            .filter_map(|p| self.tree.find(p))
+            .collect()
+    }
+
+    fn target_files(&self) -> Vec<usize> {
+        let mut files: Vec<usize> = self
+            .targets()
+            .into_iter()
+            .flat_map(|idx| self.tree.files_under(idx))
+            .collect();
+        files.sort_unstable();
+        files.dedup();
+        files
+    }
+
+    fn handle_key(&mut self, code: KeyCode) {
+        match &mut self.mode {
+            Mode::Browse => self.handle_browse_key(code),
+            Mode::Filter { input, previous } => match code {
+                KeyCode::Enter => {
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Esc => {
+                    let previous = std::mem::take(previous);
+                    self.mode = Mode::Browse;
+                    if let Err(err) = self.apply_filter(&previous) {
+                        self.status = format!("{err:#}");
+                    }
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.live_filter();
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    self.live_filter();
+                }
+                _ => {}
+            },
+            Mode::Confirm(action) => {
+                let action = *action;
+                self.mode = Mode::Browse;
+                if matches!(code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    if let Err(err) = self.remove_targets(action) {
+                        self.status = format!("{err:#}");
+                    }
+                } else {
+                    self.status = "Cancelled".to_string();
+                }
+            }
+        }
+    }
+
+    /// Re-applies the filter after each keystroke; incomplete expressions keep the last good tree.
+    fn live_filter(&mut self) {
+        let Mode::Filter { input, .. } = &self.mode else {
+            return;
+        };
+        let input = input.clone();
+        match self.apply_filter(&input) {
+            Ok(()) => self.status.clear(),
+            Err(err) => self.status = format!("{err:#}"),
+        }
+    }
+
+    fn handle_browse_key(&mut self, code: KeyCode) {
+        self.status.clear();
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+            KeyCode::PageDown => self.list.scroll_down_by(20),
+            KeyCode::PageUp => self.list.scroll_up_by(20),
+            KeyCode::Home | KeyCode::Char('g') => self.list.select_first(),
+            KeyCode::End | KeyCode::Char('G') => self.list.select_last(),
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                if let Some(idx) = self.selected().filter(|&i| self.tree.node(i).is_dir) {
+                    self.cwd = idx;
+                    self.list.select(Some(0));
+                    self.refresh_entries();
+                }
+            }
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
+                if let Some(parent) = self.tree.node(self.cwd).parent {
+                    let came_from = self.cwd;
+                    self.cwd = parent;
+                    self.refresh_entries();
+                    let pos = self.entries.iter().position(|&c| c == came_from);
+                    self.list.select(pos.or(Some(0)));
+                }
+            }
+            KeyCode::Char(' ') => {
+                if let Some(idx) = self.selected() {
+                    let path = self.tree.path_of(idx);
+                    if !self.marked.remove(&path) {
+                        self.marked.insert(path);
+                    }
+                    self.list.select_next();
+                }
+            }
+            KeyCode::Char('/') => {
+                self.mode = Mode::Filter {
+                    input: self.filter.clone(),
+                    previous: self.filter.clone(),
+                };
+            }
+            KeyCode::Char('d') | KeyCode::Char('t') => {
+                let action = if code == KeyCode::Char('t') {
+                    Action::Trash
+                } else {
+                    Action::Delete
+                };
+                if self.ctx.is_none() {
+                    self.status = "Read-only: acting on an index needs --within DIR".to_string();
+                } else if self.target_files().is_empty() {
+                    self.status = "Nothing selected".to_string();
+                } else {
+                    self.mode = Mode::Confirm(action);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Deletes or trashes the target files and drops them from the records and tree.
+    fn remove_targets(&mut self, action: Action) -> anyhow::Result<()> {
+        let mut removed = HashSet::new();
+        let mut freed: u64 = 0;
+        let mut failed: u64 = 0;
+        let targets = self.target_files();
+        let Some(ctx) = self.ctx.as_mut() else {
+            return Ok(());
+        };
+        for idx in targets {
+            let path = self.tree.path_of(idx);
+            let size = self.tree.node(idx).size;
+            let (outcome, _) = match action {
+                Action::Delete => ctx.apply("delete", &path, size, actions::delete_file)?,
+                Action::Trash => ctx.apply("trash", &path, size, trash::trash_file)?,
+            };
+            if outcome == Outcome::Done {
+                freed += size;
+                removed.insert(path);
+            } else {
+                failed += 1;
+            }
+        }
+
+        let tree = &self.tree;
+        self.records.retain(|rec| {
+            tree.find(Path::new(&rec.path))
+                .is_none_or(|idx| !removed.contains(&tree.path_of(idx)))
+        });
+        for path in &removed {
+            self.tree.remove(path);
+        }
+        self.marked.clear();
+        if self.tree.node(self.cwd).files == 0 {
+            self.cwd = self.tree.start_node();
+        }
+        self.refresh_entries();
+
+        self.status = format!(
+            "{} {} files ({}), {failed} skipped",
+            match action {
+                Action::Delete => "Deleted",
+                Action::Trash => "Trashed",
+            },
+            removed.len(),
+            format_size(freed, BINARY)
+        );
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list_area, detail_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(body);
+
+        let cwd = self.tree.node(self.cwd);
+        let title = format!(
+            " {}  {} in {} files  [{}]",
+            self.tree.path_of(self.cwd).display(),
+            format_size(cwd.size, BINARY),
+            cwd.files,
+            self.source
+        );
+        frame.render_widget(
+            Paragraph::new(title).style(Style::default().add_modifier(Modifier::BOLD)),
+            header,
+        );
+
+        let total = cwd.size.max(1);
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .map(|&idx| {
+                let node = self.tree.node(idx);
+                let mark = if self.marked.contains(&self.tree.path_of(idx)) {
+                    '*'
+                } else {
+                    ' '
+                };
+                let suffix = if node.is_dir { "/" } else { "" };
+                ListItem::new(format!(
+                    "{mark} {:>10} {:>5.1}%  {}{suffix}",
+                    format_size(node.size, BINARY),
+                    node.size as f64 * 100.0 / total as f64,
+                    node.name
+                ))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(" Tree "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        frame.render_widget(
+            Paragraph::new(self.detail_lines())
+                .block(Block::default().borders(Borders::ALL).title(" Details "))
+                .wrap(Wrap { trim: false }),
+            detail_area,
+        );
+
+        let footer_text = match &self.mode {
+            Mode::Filter { input, .. } => format!("filter> {input}_   {}", self.status),
+            Mode::Confirm(action) => {
+                let files = self.target_files();
+                let size: u64 = files.iter().map(|&i| self.tree.node(i).size).sum();
+                format!(
+                    "{} {} files ({})? [y/N]",
+                    action.name(),
+                    files.len(),
+                    format_size(size, BINARY)
+                )
+            }
+            Mode::Browse if !self.status.is_empty() => self.status.clone(),
+            Mode::Browse => {
+                "↑↓ move  ⏎ open  ⌫ up  space mark  / filter  d delete  t trash  q quit".to_string()
+            }
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+
+    fn detail_lines(&self) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+        if let Some(idx) = self.selected() {
+            let node = self.tree.node(idx);
+            lines.push(Line::from(format!(
+                "Path: {}",
+                self.tree.path_of(idx).display()
+            )));
+            lines.push(Line::from(format!(
+                "Type: {}",
+                if node.is_dir { "directory" } else { "file" }
+            )));
+            lines.push(Line::from(format!(
+                "Size: {} ({} bytes)",
+                format_size(node.size, BINARY),
+                node.size
+            )));
+            if node.is_dir {
+                lines.push(Line::from(format!("Files: {}", node.files)));
+            }
+            if let Some(mtime) = node.mtime {
+                lines.push(Line::from(format!("Modified: {}", format_unix_secs(mtime))));
+            }
+        }
+        lines.push(Line::from(""));
+        if !self.filter.is_empty() {
+            lines.push(Line::from(format!("Filter: {}", self.filter)));
+        }
+        if !self.marked.is_empty() {
+            let size: u64 = self
+                .marked
+                .iter()
+                .filter_map(|p| self.tree.find(p))
+                .map(|i| self.tree.node(i).size)
+                .sum();
+            lines.push(Line::from(format!(
+                "Marked: {} items ({})",
+                self.marked.len(),
+                format_size(size, BINARY)
+            )));
+        }
+        lines
+    }
+}
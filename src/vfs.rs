@@ -0,0 +1,511 @@
+//! The filesystem a [`Scanner`](crate::Scanner) walks: the real one, or an in-memory tree for tests
+//! that need permission errors, symlink loops, sparse files, hard links or mount points on demand.
+
+use crate::index::unix_secs;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+/// Symlinks followed while resolving one path before giving up, as Linux does.
+const MAX_SYMLINK_HOPS: u32 = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    /// Devices, sockets, FIFOs
+    Other,
+}
+
+/// Identifies a file across its hard links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    pub dev: u64,
+    pub ino: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: FileKind,
+    /// Apparent size; a sparse file may take up less
+    pub len: u64,
+    /// `None` where the platform does not expose device and inode numbers
+    pub id: Option<FileId>,
+    /// Seconds since the Unix epoch
+    pub mtime: Option<i64>,
+    pub atime: Option<i64>,
+}
+
+/// An open file, readable and seekable (zip archives are read from the end).
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// The operations a scan needs.
+pub trait Filesystem {
+    /// Metadata of what `path` points to, following symlinks.
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Metadata of `path` itself, a symlink if it is one.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// The paths of the entries in the directory `path`, each `path` joined with a name.
+    fn read_dir<'a>(
+        &'a self,
+        path: &Path,
+    ) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>>;
+
+    fn open<'a>(&'a self, path: &Path) -> io::Result<Box<dyn ReadSeek + 'a>>;
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+impl<T: Filesystem + ?Sized> Filesystem for &T {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        (**self).metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        (**self).symlink_metadata(path)
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &Path,
+    ) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>> {
+        (**self).read_dir(path)
+    }
+
+    fn open<'a>(&'a self, path: &Path) -> io::Result<Box<dyn ReadSeek + 'a>> {
+        (**self).open(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        (**self).canonicalize(path)
+    }
+}
+
+/// The operating system's filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl From<std::fs::Metadata> for Metadata {
+    fn from(md: std::fs::Metadata) -> Self {
+        let file_type = md.file_type();
+        let kind = if file_type.is_file() {
+            FileKind::File
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::Other
+        };
+        #[cfg(unix)]
+        let id = {
+            use std::os::unix::fs::MetadataExt;
+            Some(FileId {
+                dev: md.dev(),
+                ino: md.ino(),
+            })
+        };
+        #[cfg(not(unix))]
+        let id = None;
+        Metadata {
+            kind,
+            len: md.len(),
+            id,
+            mtime: md.modified().ok().and_then(unix_secs),
+            atime: md.accessed().ok().and_then(unix_secs),
+        }
+    }
+}
+
+impl Filesystem for RealFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        std::fs::metadata(path).map(Metadata::from)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        std::fs::symlink_metadata(path).map(Metadata::from)
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &Path,
+    ) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>> {
+        let entries = std::fs::read_dir(path)?;
+        Ok(Box::new(entries.map(|entry| entry.map(|e| e.path()))))
+    }
+
+    fn open<'a>(&'a self, path: &Path) -> io::Result<Box<dyn ReadSeek + 'a>> {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+}
+
+/// An in-memory directory tree, built up with paths from `/`. Directories are created as needed.
+///
+/// ```
+/// # use largest_file_finder::vfs::{Filesystem, MemFs};
+/// # use std::path::Path;
+/// let mut fs = MemFs::new();
+/// fs.file("/data/a.txt", "hello")
+///     .sparse_file("/data/disk.img", 1 << 40)
+///     .hard_link("/data/a.txt", "/data/b.txt")
+///     .symlink("/data", "/data/loop")
+///     .mount("/data/usb")
+///     .deny("/data/private");
+/// assert_eq!(fs.metadata(Path::new("/data/loop/disk.img"))?.len, 1 << 40);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemFs {
+    nodes: BTreeMap<PathBuf, Node>,
+    files: HashMap<u64, FileData>,
+    /// Directories that cannot be listed and files that cannot be read
+    denied: HashSet<PathBuf>,
+    next_ino: u64,
+    next_dev: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Dir(FileId),
+    /// Inode number, shared by hard links
+    File(u64),
+    Symlink(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct FileData {
+    id: FileId,
+    len: u64,
+    /// `None` for a sparse file, which reads as zeros
+    data: Option<Vec<u8>>,
+}
+
+impl Default for MemFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        let root = FileId { dev: 1, ino: 1 };
+        Self {
+            nodes: BTreeMap::from([(PathBuf::from("/"), Node::Dir(root))]),
+            files: HashMap::new(),
+            denied: HashSet::new(),
+            next_ino: 2,
+            next_dev: 2,
+        }
+    }
+
+    pub fn dir(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.make_dir(path.as_ref());
+        self
+    }
+
+    pub fn file(&mut self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> &mut Self {
+        let contents = contents.into();
+        self.add_file(path.as_ref(), contents.len() as u64, Some(contents));
+        self
+    }
+
+    /// A file of `len` bytes that takes up no memory and reads as zeros.
+    pub fn sparse_file(&mut self, path: impl AsRef<Path>, len: u64) -> &mut Self {
+        self.add_file(path.as_ref(), len, None);
+        self
+    }
+
+    /// Another name for the file at `existing`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no file at `existing`.
+    pub fn hard_link(&mut self, existing: impl AsRef<Path>, new: impl AsRef<Path>) -> &mut Self {
+        let existing = absolute(existing.as_ref());
+        let Some(Node::File(ino)) = self.nodes.get(&existing) else {
+            panic!("no file to link at {}", existing.display());
+        };
+        let node = Node::File(*ino);
+        let new = self.make_parent(new.as_ref());
+        self.nodes.insert(new, node);
+        self
+    }
+
+    /// A symlink at `link` pointing to `target`, which is resolved relative to the link's
+    /// directory unless absolute.
+    pub fn symlink(&mut self, target: impl AsRef<Path>, link: impl AsRef<Path>) -> &mut Self {
+        let link = self.make_parent(link.as_ref());
+        self.nodes
+            .insert(link, Node::Symlink(target.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Makes `path` the mount point of a new device; what is created below it afterwards lives on
+    /// that device.
+    pub fn mount(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        let path = self.make_dir(path.as_ref());
+        let id = FileId {
+            dev: self.next_dev,
+            ino: 1,
+        };
+        self.next_dev += 1;
+        self.nodes.insert(path, Node::Dir(id));
+        self
+    }
+
+    /// Listing the directory, or reading the file, at `path` fails with permission denied. Its
+    /// metadata can still be read, as with a real directory lacking read permission.
+    pub fn deny(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        let path = absolute(path.as_ref());
+        if !self.nodes.contains_key(&path) {
+            self.make_dir(&path);
+        }
+        self.denied.insert(path);
+        self
+    }
+
+    fn inode(&mut self, dev: u64) -> FileId {
+        let id = FileId {
+            dev,
+            ino: self.next_ino,
+        };
+        self.next_ino += 1;
+        id
+    }
+
+    fn make_dir(&mut self, path: &Path) -> PathBuf {
+        let path = absolute(path);
+        match self.nodes.get(&path) {
+            Some(Node::Dir(_)) => {}
+            Some(_) => panic!("{} is not a directory", path.display()),
+            None => {
+                self.make_parent(&path);
+                let id = self.inode(self.dev_of_parent(&path));
+                self.nodes.insert(path.clone(), Node::Dir(id));
+            }
+        }
+        path
+    }
+
+    /// Creates the parent directories of `path`, returning it made absolute.
+    fn make_parent(&mut self, path: &Path) -> PathBuf {
+        let path = absolute(path);
+        if let Some(parent) = path.parent() {
+            self.make_dir(parent);
+        }
+        path
+    }
+
+    fn dev_of_parent(&self, path: &Path) -> u64 {
+        match path.parent().and_then(|p| self.nodes.get(p)) {
+            Some(Node::Dir(id)) => id.dev,
+            _ => 1,
+        }
+    }
+
+    fn add_file(&mut self, path: &Path, len: u64, data: Option<Vec<u8>>) {
+        let path = self.make_parent(path);
+        let id = self.inode(self.dev_of_parent(&path));
+        self.files.insert(id.ino, FileData { id, len, data });
+        self.nodes.insert(path, Node::File(id.ino));
+    }
+
+    /// The path of the node `path` names, with symlinks in it resolved (the last one only with
+    /// `follow_last`).
+    fn resolve(&self, path: &Path, follow_last: bool) -> io::Result<PathBuf> {
+        let mut resolved = PathBuf::from("/");
+        let mut rest: VecDeque<OsString> = names(path).collect();
+        let mut hops = 0;
+        while let Some(name) = rest.pop_front() {
+            if name == ".." {
+                resolved.pop();
+                continue;
+            }
+            let candidate = resolved.join(&name);
+            match self.nodes.get(&candidate) {
+                None => return Err(io::ErrorKind::NotFound.into()),
+                Some(Node::Symlink(target)) if follow_last || !rest.is_empty() => {
+                    hops += 1;
+                    if hops > MAX_SYMLINK_HOPS {
+                        return Err(io::Error::other("too many levels of symbolic links"));
+                    }
+                    if target.is_absolute() {
+                        resolved = PathBuf::from("/");
+                    }
+                    for name in names(target).collect::<Vec<_>>().into_iter().rev() {
+                        rest.push_front(name);
+                    }
+                }
+                Some(Node::File(_)) if !rest.is_empty() => {
+                    return Err(io::ErrorKind::NotADirectory.into());
+                }
+                Some(_) => resolved = candidate,
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn node_metadata(&self, resolved: &Path) -> Metadata {
+        let (kind, len, id) = match &self.nodes[resolved] {
+            Node::Dir(id) => (FileKind::Dir, 0, *id),
+            Node::File(ino) => {
+                let file = &self.files[ino];
+                (FileKind::File, file.len, file.id)
+            }
+            Node::Symlink(target) => (
+                FileKind::Symlink,
+                target.as_os_str().len() as u64,
+                FileId { dev: 0, ino: 0 },
+            ),
+        };
+        Metadata {
+            kind,
+            len,
+            id: (kind != FileKind::Symlink).then_some(id),
+            mtime: None,
+            atime: None,
+        }
+    }
+
+    fn check_access(&self, resolved: &Path) -> io::Result<()> {
+        if self.denied.contains(resolved) {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        Ok(())
+    }
+}
+
+impl Filesystem for MemFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(self.node_metadata(&self.resolve(path, true)?))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(self.node_metadata(&self.resolve(path, false)?))
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &Path,
+    ) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>> {
+        let dir = self.resolve(path, true)?;
+        if !matches!(self.nodes[&dir], Node::Dir(_)) {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        self.check_access(&dir)?;
+        let path = path.to_path_buf();
+        let depth = names(&dir).count() + 1;
+        let children = self
+            .nodes
+            .range(dir.clone()..)
+            .skip(1)
+            .take_while(move |(child, _)| child.starts_with(&dir))
+            .filter(move |(child, _)| names(child).count() == depth)
+            .filter_map(move |(child, _)| child.file_name().map(|name| Ok(path.join(name))));
+        Ok(Box::new(children))
+    }
+
+    fn open<'a>(&'a self, path: &Path) -> io::Result<Box<dyn ReadSeek + 'a>> {
+        let resolved = self.resolve(path, true)?;
+        let Node::File(ino) = &self.nodes[&resolved] else {
+            return Err(io::ErrorKind::IsADirectory.into());
+        };
+        self.check_access(&resolved)?;
+        let file = &self.files[ino];
+        Ok(match &file.data {
+            Some(data) => Box::new(io::Cursor::new(data.as_slice())),
+            None => Box::new(Zeros {
+                len: file.len,
+                pos: 0,
+            }),
+        })
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.resolve(path, true)
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    let mut abs = PathBuf::from("/");
+    abs.extend(names(path));
+    abs
+}
+
+/// The names in `path`, with `..` kept and `.` dropped.
+fn names(path: &Path) -> impl Iterator<Item = OsString> + '_ {
+    path.components().filter_map(|c| match c {
+        Component::Normal(name) => Some(name.to_os_string()),
+        Component::ParentDir => Some(OsString::from("..")),
+        Component::RootDir | Component::CurDir | Component::Prefix(_) => None,
+    })
+}
+
+/// The contents of a sparse [`MemFs`] file.
+struct Zeros {
+    len: u64,
+    pos: u64,
+}
+
+impl Read for Zeros {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.len.saturating_sub(self.pos) as usize);
+        buf[..n].fill(0);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Zeros {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_symlinks_and_lists_only_direct_children() {
+        let mut fs = MemFs::new();
+        fs.file("/a/x", "1")
+            .file("/a/sub/y", "22")
+            .file("/ab", "")
+            .symlink("../a", "/b/up")
+            .symlink("/c/self", "/c/self");
+
+        let listed: Vec<PathBuf> = fs
+            .read_dir(Path::new("/b/up"))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(listed, [Path::new("/b/up/sub"), Path::new("/b/up/x")]);
+        assert_eq!(fs.metadata(Path::new("/b/up/sub/y")).unwrap().len, 2);
+        assert_eq!(
+            fs.symlink_metadata(Path::new("/b/up")).unwrap().kind,
+            FileKind::Symlink
+        );
+        assert!(fs.metadata(Path::new("/c/self")).is_err());
+        assert_eq!(
+            fs.metadata(Path::new("/a/x/z")).unwrap_err().kind(),
+            io::ErrorKind::NotADirectory
+        );
+    }
+}
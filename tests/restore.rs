@@ -0,0 +1,44 @@
+//! Trash and restore through the binary, run from different working directories.
+
+use std::path::Path;
+use std::process::Command;
+
+fn run(cwd: &Path, home: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_largest-file-finder"))
+        .args(args)
+        .current_dir(cwd)
+        .env("XDG_DATA_HOME", home)
+        .status()
+        .unwrap();
+    assert!(status.success(), "{args:?} failed");
+}
+
+#[cfg(unix)]
+#[test]
+fn restore_from_another_directory_puts_files_back() {
+    let base = std::env::temp_dir().join(format!("lff-restore-cwd-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let file = base.join("work/data/big.bin");
+    std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+    std::fs::create_dir_all(base.join("elsewhere")).unwrap();
+    std::fs::write(&file, vec![7; 4096]).unwrap();
+    let home = base.join("share");
+    let log = base.join("audit.jsonl");
+    let log = log.to_str().unwrap();
+
+    run(
+        &base.join("work"),
+        &home,
+        &["scan", "data", "--trash", "--yes", "--audit-log", log],
+    );
+    assert!(!file.exists());
+
+    run(
+        &base.join("elsewhere"),
+        &home,
+        &["restore", "--yes", "--audit-log", log],
+    );
+    assert_eq!(std::fs::read(&file).unwrap(), vec![7; 4096]);
+    assert!(!base.join("elsewhere/data").exists());
+    std::fs::remove_dir_all(&base).unwrap();
+}
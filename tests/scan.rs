@@ -0,0 +1,233 @@
+//! End-to-end scans of in-memory trees.
+
+use largest_file_finder::vfs::{Filesystem, MemFs, Metadata, ReadSeek};
+use largest_file_finder::{
+    GroupBy, IndexEntry, IndexWriter, Matcher, Ranking, ScanOutcome, Scanner, TopN,
+};
+use std::cell::Cell;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Scans with `scanner`, returning the matched paths in walk order.
+fn matched(scanner: &Scanner<&MemFs>) -> (Vec<String>, ScanOutcome) {
+    let mut entries: Vec<IndexEntry> = Vec::new();
+    let outcome = scanner.scan(&mut [&mut entries]).unwrap();
+    (entries.into_iter().map(|e| e.path).collect(), outcome)
+}
+
+fn tree() -> MemFs {
+    let mut fs = MemFs::new();
+    fs.file("/data/a.iso", vec![0; 300])
+        .file("/data/b.txt", vec![0; 20])
+        .file("/data/cache/c.bin", vec![0; 500])
+        .file("/data/docs/d.pdf", vec![0; 100]);
+    fs
+}
+
+#[test]
+fn one_pass_ranks_groups_and_indexes() {
+    let fs = tree();
+    let scanner = Scanner::with_filesystem(&fs, "/data")
+        .matcher(Matcher::new().exclude("/cache/").unwrap())
+        .min_bytes(50);
+    let mut top = TopN::new(1, Ranking::by_size());
+    let mut by_ext = GroupBy::extension();
+    let mut index = IndexWriter::new(Vec::new());
+
+    let outcome = scanner
+        .scan(&mut [&mut top, &mut by_ext, &mut index])
+        .unwrap();
+
+    assert_eq!((outcome.files, outcome.bytes, outcome.pruned), (3, 420, 1));
+    assert_eq!(outcome.errors.total(), 0);
+    let best = top.into_sorted_vec();
+    assert_eq!(best[0].path.to_str(), Some("/data/a.iso"));
+    let mut exts: Vec<&str> = by_ext.groups().keys().map(String::as_str).collect();
+    exts.sort();
+    assert_eq!(exts, ["iso", "pdf"]);
+    // The index records every file walked, including those below the minimum size.
+    let index = String::from_utf8(index.finish().unwrap()).unwrap();
+    assert_eq!(index.lines().count(), 3);
+}
+
+#[test]
+fn unreadable_directory_is_reported_and_the_rest_scanned() {
+    let mut fs = tree();
+    fs.file("/data/private/secret", vec![0; 9])
+        .deny("/data/private");
+
+    let (paths, outcome) = matched(&Scanner::with_filesystem(&fs, "/data"));
+
+    assert!(!paths.iter().any(|p| p.contains("private")));
+    assert_eq!(paths.len(), 4);
+    assert_eq!(outcome.errors.total(), 1);
+    let summary = outcome.errors.summary(outcome.files, outcome.bytes);
+    assert!(summary.contains("permission-denied 1"), "{summary}");
+    assert!(summary.contains("/data/private (1)"), "{summary}");
+}
+
+#[test]
+fn symlinks_are_skipped_unless_followed_and_loops_are_reported() {
+    let mut fs = tree();
+    fs.symlink("docs", "/data/docs-link")
+        .symlink("/data", "/data/docs/up")
+        .symlink("missing", "/data/dangling");
+
+    let (paths, outcome) = matched(&Scanner::with_filesystem(&fs, "/data"));
+    assert_eq!(paths.len(), 4);
+    assert_eq!(outcome.errors.total(), 0);
+
+    let (paths, outcome) = matched(&Scanner::with_filesystem(&fs, "/data").follow_symlinks(true));
+    assert!(paths.contains(&"/data/docs-link/d.pdf".to_string()));
+    assert_eq!(paths.len(), 5);
+    let summary = outcome.errors.summary(outcome.files, outcome.bytes);
+    // docs/up and docs-link/up both lead back to /data; the dangling link has vanished.
+    assert!(summary.contains("loop 2"), "{summary}");
+    assert!(summary.contains("vanished 1"), "{summary}");
+}
+
+#[test]
+fn sparse_files_rank_by_apparent_size() {
+    let mut fs = tree();
+    fs.sparse_file("/data/vm/disk.img", 1 << 40);
+
+    let mut top = TopN::new(1, Ranking::by_size());
+    let outcome = Scanner::with_filesystem(&fs, "/data")
+        .scan(&mut [&mut top])
+        .unwrap();
+
+    let best = top.into_sorted_vec();
+    assert_eq!(best[0].path.to_str(), Some("/data/vm/disk.img"));
+    assert_eq!(best[0].size, 1 << 40);
+    assert_eq!(outcome.bytes, (1 << 40) + 920);
+}
+
+#[test]
+fn hard_links_are_listed_under_every_name() {
+    let mut fs = tree();
+    fs.hard_link("/data/a.iso", "/data/backup/a.iso");
+
+    let (paths, outcome) = matched(&Scanner::with_filesystem(&fs, "/data"));
+
+    assert!(paths.contains(&"/data/a.iso".to_string()));
+    assert!(paths.contains(&"/data/backup/a.iso".to_string()));
+    assert_eq!((outcome.files, outcome.bytes), (5, 1220));
+}
+
+#[test]
+fn one_file_system_stops_at_mount_points() {
+    let mut fs = tree();
+    fs.mount("/data/usb")
+        .file("/data/usb/photo.jpg", vec![0; 40]);
+
+    let (paths, _) = matched(&Scanner::with_filesystem(&fs, "/data"));
+    assert!(paths.contains(&"/data/usb/photo.jpg".to_string()));
+
+    let (paths, outcome) = matched(&Scanner::with_filesystem(&fs, "/data").one_file_system(true));
+    assert!(!paths.iter().any(|p| p.starts_with("/data/usb")));
+    assert_eq!(paths.len(), 4);
+    assert_eq!(outcome.errors.total(), 0);
+}
+
+#[test]
+fn ignore_files_are_read_from_the_scanned_tree() {
+    let mut fs = tree();
+    fs.file("/data/.gitignore", "cache/\n*.txt\n")
+        .file("/data/docs/.ignore", "!*.pdf\n*.bak\n")
+        .file("/data/docs/old.bak", "x");
+
+    let (mut paths, outcome) = matched(&Scanner::with_filesystem(&fs, "/data").ignore_files(true));
+
+    paths.retain(|p| !p.ends_with("ignore"));
+    assert_eq!(paths, ["/data/a.iso", "/data/docs/d.pdf"]);
+    assert_eq!(outcome.pruned, 1);
+}
+
+#[test]
+fn a_file_root_is_scanned_as_itself() {
+    let fs = tree();
+
+    let (paths, outcome) = matched(&Scanner::with_filesystem(&fs, "/data/a.iso"));
+
+    assert_eq!(paths, ["/data/a.iso"]);
+    assert_eq!((outcome.files, outcome.bytes), (1, 300));
+}
+
+/// A [`MemFs`] that tracks how many directory listings are open at once.
+struct CountingFs {
+    inner: MemFs,
+    open: Cell<usize>,
+    peak: Cell<usize>,
+}
+
+/// A directory listing of a [`CountingFs`], counted until dropped.
+struct Listing<'a> {
+    fs: &'a CountingFs,
+    children: Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>,
+}
+
+impl Iterator for Listing<'_> {
+    type Item = io::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.children.next()
+    }
+}
+
+impl Drop for Listing<'_> {
+    fn drop(&mut self) {
+        self.fs.open.set(self.fs.open.get() - 1);
+    }
+}
+
+impl Filesystem for CountingFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &Path,
+    ) -> io::Result<Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>> {
+        let children = self.inner.read_dir(path)?;
+        self.open.set(self.open.get() + 1);
+        self.peak.set(self.peak.get().max(self.open.get()));
+        Ok(Box::new(Listing { fs: self, children }))
+    }
+
+    fn open<'a>(&'a self, path: &Path) -> io::Result<Box<dyn ReadSeek + 'a>> {
+        self.inner.open(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+}
+
+#[test]
+fn deep_trees_keep_few_directories_open() {
+    let mut inner = MemFs::new();
+    let mut dir = PathBuf::from("/deep");
+    for level in 0..300 {
+        inner.file(dir.join(format!("f{level}")), vec![0; 1]);
+        dir.push("d");
+    }
+    let fs = CountingFs {
+        inner,
+        open: Cell::new(0),
+        peak: Cell::new(0),
+    };
+
+    let mut entries: Vec<IndexEntry> = Vec::new();
+    let outcome = Scanner::with_filesystem(&fs, "/deep")
+        .scan(&mut [&mut entries])
+        .unwrap();
+
+    assert_eq!((outcome.files, outcome.errors.total()), (300, 0));
+    assert_eq!(entries.len(), 300);
+    assert!(fs.peak.get() <= 10, "{} directories open", fs.peak.get());
+}